@@ -0,0 +1,299 @@
+use anyhow::{anyhow, Context, Result};
+use bili_ticket_core::buy::{self, BuyOptions, TicketInfo};
+use bili_ticket_core::event::EventSink;
+use bili_ticket_core::http::ClientPool;
+use bili_ticket_core::task::{TaskHandle, TaskRecord};
+use bili_ticket_core::config::{Config, Endpoints, Settings};
+use bili_ticket_core::{api, storage, timesync};
+use bili_ticket_core::clock::Clock;
+use bili_ticket_core::timesync::TimeSource;
+use chrono::Local;
+use clap::{Parser, Subcommand};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Parser)]
+#[command(name = "bili-ticket", version, about = "Run biliTickerBuy tasks without the GUI")]
+struct Cli {
+    /// Directory holding accounts.json, history.json, config.json and settings.json
+    #[arg(long, global = true, default_value = ".")]
+    data_dir: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List accounts stored in accounts.json
+    Accounts,
+    /// Show screens and SKUs of a project
+    Project {
+        id: String,
+        /// Print the raw API response
+        #[arg(long)]
+        json: bool,
+    },
+    /// List buyers of an account for a project
+    Buyers {
+        #[arg(long)]
+        project: String,
+        /// Account uid; may be omitted when only one account is stored
+        #[arg(long)]
+        account: Option<String>,
+        #[arg(long)]
+        json: bool,
+    },
+    /// List delivery addresses of an account
+    Addresses {
+        #[arg(long)]
+        account: Option<String>,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Compare local time with an HTTP time API or NTP server
+    SyncTime {
+        /// Use only this server instead of the time sources in settings.json
+        #[arg(long)]
+        server: Option<String>,
+        /// Number of samples to combine
+        #[arg(long, default_value_t = timesync::DEFAULT_SAMPLES)]
+        samples: u32,
+    },
+    /// Run a buy task described by a JSON config file
+    Buy { config: PathBuf },
+}
+
+/// Buy task config file. Field names follow the GUI's `start_buy`
+/// arguments; omitted options fall back to settings.json.
+#[derive(Deserialize)]
+struct BuyConfig {
+    /// Account uid whose cookies are used for the task
+    account: Option<String>,
+    ticket_info: TicketInfo,
+    interval: Option<u64>,
+    /// 0: retry until stopped, 1: stop after `total_attempts` rounds
+    mode: Option<u32>,
+    total_attempts: Option<u32>,
+    time_start: Option<String>,
+    proxy: Option<String>,
+    /// Server minus local time in ms; synced from `ntp_server` when absent
+    time_offset: Option<f64>,
+    buyers: Option<Vec<Value>>,
+    ntp_server: Option<String>,
+    time_sources: Option<Vec<TimeSource>>,
+    /// Stop the other tasks for the same account and buyers once this one orders
+    exclusive: Option<bool>,
+    /// Poll the project and only buy while the SKU is in stock
+    watch: Option<bool>,
+    watch_interval: Option<u64>,
+    /// Schedule the task for the SKU's published sale start
+    at_sale_start: Option<bool>,
+    /// Give up at this local time, `%Y-%m-%d %H:%M:%S`
+    time_end: Option<String>,
+    max_prepare_failures: Option<u32>,
+    max_requests: Option<u32>,
+    stop_errnos: Option<Vec<i64>>,
+}
+
+/// Prints task events to stdout and remembers whether the task succeeded.
+#[derive(Default)]
+struct StdoutSink {
+    success: AtomicBool,
+}
+
+impl EventSink for StdoutSink {
+    fn emit(&self, event: &str, payload: Value) -> bili_ticket_core::error::Result<()> {
+        let now = Local::now().format("%H:%M:%S%.3f");
+        match event {
+            "log" => println!("{} {}", now, payload["message"].as_str().unwrap_or_default()),
+            "payment_ready" => println!("{} Payment URL: {}", now, payload["url"].as_str().unwrap_or_default()),
+            "task_phase" => println!("{} Phase: {}", now, payload["phase"].as_str().unwrap_or_default()),
+            // Already covered by the log lines
+            "countdown" | "prepare_result" | "attempt_result" | "sku_switched" | "order_created" => {}
+            "task_finished" => {
+                if payload["success"].as_bool().unwrap_or(false) {
+                    self.success.store(true, Ordering::Relaxed);
+                }
+                println!("{} Result: {}", now, payload["message"].as_str().unwrap_or_default());
+            }
+            _ => println!("{} {}: {}", now, event, payload),
+        }
+        Ok(())
+    }
+}
+
+fn find_account(data_dir: &Path, uid: Option<&str>) -> Result<storage::Account> {
+    let accounts = storage::get_accounts(data_dir)?;
+    match uid {
+        Some(uid) => accounts.into_iter()
+            .find(|a| a.uid == uid)
+            .ok_or_else(|| anyhow!("Account {} not found in {:?}", uid, data_dir.join("accounts.json"))),
+        None => match accounts.len() {
+            0 => Err(anyhow!("No accounts in {:?}", data_dir.join("accounts.json"))),
+            1 => Ok(accounts.into_iter().next().unwrap()),
+            _ => Err(anyhow!("Several accounts stored, pass --account <uid>")),
+        },
+    }
+}
+
+fn print_json(value: &Value) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn list_accounts(data_dir: &Path) -> Result<()> {
+    for a in storage::get_accounts(data_dir)? {
+        println!("{}\t{}\tLv{}{}", a.uid, a.name, a.level, if a.is_vip { "\tVIP" } else { "" });
+    }
+    Ok(())
+}
+
+async fn show_project(pool: &ClientPool, endpoints: &Endpoints, id: String, json: bool) -> Result<()> {
+    let res = api::fetch_project_info(&pool.anonymous(), endpoints, id).await?;
+    if json {
+        return print_json(&serde_json::to_value(&res)?);
+    }
+    let project = res.into_data()?;
+    println!("{} ({})", project.name, project.id);
+    for screen in &project.screen_list {
+        println!("  screen {}\t{}", screen.id, screen.name);
+        for sku in &screen.ticket_list {
+            println!("    sku {}\t{}\t¥{:.2}", sku.id, sku.desc, sku.price as f64 / 100.0);
+        }
+    }
+    Ok(())
+}
+
+async fn show_buyers(pool: &ClientPool, endpoints: &Endpoints, data_dir: &Path, project: String, uid: Option<String>, json: bool) -> Result<()> {
+    let account = find_account(data_dir, uid.as_deref())?;
+    let client = pool.for_cookies(&account.cookies)?;
+    let buyers = api::fetch_buyers(&client, endpoints, project).await?.into_data()?.list;
+    if json {
+        return print_json(&serde_json::to_value(&buyers)?);
+    }
+    for b in &buyers {
+        println!("{}\t{}\t{}\t{}", b.id, b.name, b.personal_id, b.tel);
+    }
+    Ok(())
+}
+
+async fn show_addresses(pool: &ClientPool, endpoints: &Endpoints, data_dir: &Path, uid: Option<String>, json: bool) -> Result<()> {
+    let account = find_account(data_dir, uid.as_deref())?;
+    let client = pool.for_cookies(&account.cookies)?;
+    let addresses = api::fetch_address_list(&client, endpoints).await?.into_data()?.addr_list;
+    if json {
+        return print_json(&serde_json::to_value(&addresses)?);
+    }
+    for a in &addresses {
+        println!("{}\t{}\t{}\t{}{}{}{}", a.id, a.name, a.phone, a.prov, a.city, a.area, a.addr);
+    }
+    Ok(())
+}
+
+async fn run_buy(pool: &ClientPool, endpoints: &Endpoints, data_dir: &Path, config_path: &Path) -> Result<bool> {
+    let content = fs::read_to_string(config_path)
+        .with_context(|| format!("Failed to read config file: {:?}", config_path))?;
+    let config: BuyConfig = serde_json::from_str(&content)
+        .with_context(|| format!("Invalid config file: {:?}", config_path))?;
+
+    let mut info = config.ticket_info;
+    if config.account.is_some() || info.cookies.is_empty() {
+        info.cookies = find_account(data_dir, config.account.as_deref())?.cookies;
+    }
+    if let Some(b) = config.buyers {
+        info.apply_buyers(b);
+    }
+
+    let settings = Settings::load(data_dir)?;
+    let defaults = BuyOptions::from_settings(&settings);
+    let mut options = BuyOptions {
+        interval: config.interval.unwrap_or(defaults.interval),
+        mode: config.mode.unwrap_or(defaults.mode),
+        total_attempts: config.total_attempts.unwrap_or(defaults.total_attempts),
+        time_start: config.time_start,
+        proxy: config.proxy.or(defaults.proxy),
+        time_offset: config.time_offset,
+        ntp_server: config.ntp_server.or(defaults.ntp_server),
+        time_sources: config.time_sources.unwrap_or(defaults.time_sources),
+        exclusive: config.exclusive.unwrap_or(defaults.exclusive),
+        watch: config.watch.unwrap_or(defaults.watch),
+        watch_interval: config.watch_interval.unwrap_or(defaults.watch_interval),
+        at_sale_start: config.at_sale_start.unwrap_or(defaults.at_sale_start),
+        time_end: config.time_end,
+        max_prepare_failures: config.max_prepare_failures.unwrap_or(defaults.max_prepare_failures),
+        max_requests: config.max_requests.unwrap_or(defaults.max_requests),
+        stop_errnos: config.stop_errnos.unwrap_or(defaults.stop_errnos),
+    };
+    buy::apply_sale_start(&pool.anonymous(), endpoints, &info, &mut options).await?;
+    if let Some(time_start) = options.time_start.as_deref().filter(|_| options.at_sale_start) {
+        println!("Sale starts at {}", time_start);
+    }
+
+    // Same as the GUI: one clock, synced before starting and kept fresh while waiting
+    let clock = Clock::new(pool.anonymous(), timesync::sources(&options.time_sources, options.ntp_server.as_deref(), endpoints));
+    match options.time_offset {
+        Some(o) => clock.set_offset(o as i64),
+        None => match clock.sync().await {
+            Ok(sync) => println!("Time synced with {}", sync.source),
+            Err(e) => eprintln!("Time sync failed, using local clock: {}", e),
+        },
+    }
+    if options.time_offset.is_none() {
+        clock.set_refresh_interval(Duration::from_millis(settings.sync_interval));
+        tokio::spawn(clock.clone().refresh());
+    }
+
+    let task = TaskHandle::new(TaskRecord::new(Uuid::new_v4().to_string(), &info, &options));
+    let sink = Arc::new(StdoutSink::default());
+
+    let ctrl_c_task = task.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            ctrl_c_task.stop();
+        }
+    });
+
+    let client = pool.for_cookies(&info.cookies)?;
+    buy::start_buy_task(sink.clone(), client, endpoints.clone(), clock, task, info, options, data_dir.to_path_buf()).await?;
+
+    Ok(sink.success.load(Ordering::Relaxed))
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    let cli = Cli::parse();
+    let endpoints = Config::load(&cli.data_dir)?.endpoints;
+    let pool = ClientPool::new(endpoints.clone())?;
+
+    match cli.command {
+        Command::Accounts => list_accounts(&cli.data_dir)?,
+        Command::Project { id, json } => show_project(&pool, &endpoints, id, json).await?,
+        Command::Buyers { project, account, json } => show_buyers(&pool, &endpoints, &cli.data_dir, project, account, json).await?,
+        Command::Addresses { account, json } => show_addresses(&pool, &endpoints, &cli.data_dir, account, json).await?,
+        Command::SyncTime { server, samples } => {
+            let sources = match server {
+                Some(server) => vec![TimeSource::from_url(&server)],
+                None => {
+                    let settings = Settings::load(&cli.data_dir)?;
+                    timesync::sources(&settings.time_sources, settings.ntp_server.as_deref(), &endpoints)
+                }
+            };
+            let clock = Clock::new(pool.anonymous(), Vec::new());
+            print_json(&api::sync_time(&clock, &sources, samples).await?)?
+        }
+        Command::Buy { config } => {
+            if !run_buy(&pool, &endpoints, &cli.data_dir, &config).await? {
+                std::process::exit(1);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use reqwest::Client;
+use serde_json::Value;
+use crate::error::{Error, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::config::Endpoints;
+use crate::http::ClientPool;
+use crate::models::{AddressList, ApiResponse, BuyerList, LinkGoodsDetail, LinkGoodsList, Project, UserNav};
+use crate::storage::Account;
+use crate::clock::Clock;
+use crate::timesync::TimeSource;
+use log::warn;
+
+async fn get_json(client: &Client, url: &str) -> Result<Value> {
+    Ok(client.get(url).send().await?.json().await?)
+}
+
+/// Project details with linked goods (场贩/周边) appended to `screen_list`
+/// and the express fee folded into each SKU price.
+pub async fn fetch_project_info(client: &Client, endpoints: &Endpoints, id: String) -> Result<ApiResponse<Project>> {
+    let mut res: ApiResponse<Project> = ApiResponse::parse(get_json(client, &endpoints.project_info(&id)).await?, "project/getV2")?;
+
+    let Some(project) = res.data.as_mut() else {
+        return Ok(res);
+    };
+
+    // Check for linked goods (场贩/周边); they are optional, so failures are only logged
+    let link_goods = match get_json(client, &endpoints.linkgoods_list(&id)).await
+        .and_then(|raw| ApiResponse::<LinkGoodsList>::parse(raw, "linkgoods/list"))
+    {
+        Ok(list) => list.data.map(|d| d.list).unwrap_or_default(),
+        Err(e) => {
+            warn!("Failed to fetch linked goods of {}: {}", id, e);
+            Vec::new()
+        }
+    };
+
+    // Parallelize detail fetching
+    let mut tasks = Vec::new();
+    for item in link_goods {
+        let link_id = item.id.to_string();
+        let client_clone = client.clone();
+        let detail_url = endpoints.linkgoods_detail(&link_id);
+
+        tasks.push(tokio::spawn(async move {
+            let detail = get_json(&client_clone, &detail_url).await
+                .and_then(|raw| ApiResponse::<LinkGoodsDetail>::parse(raw, "linkgoods/detail"))
+                .and_then(|res| res.into_data());
+            (link_id, detail)
+        }));
+    }
+
+    // Collect results
+    for task in tasks {
+        let Ok((link_id, detail)) = task.await else { continue };
+        match detail {
+            Ok(detail) => {
+                for mut spec in detail.specs_list {
+                    spec.project_id = Some(detail.item_id); // Use actual item_id from detail
+                    spec.link_id = Some(link_id.clone());
+                    project.screen_list.push(spec);
+                }
+            }
+            Err(e) => warn!("Failed to fetch linked goods {}: {}", link_id, e),
+        }
+    }
+
+    // Apply express_fee logic (Match Python TicketService.py)
+    if !project.has_eticket {
+        for screen in &mut project.screen_list {
+            if screen.express_fee > 0 {
+                for sku in &mut screen.ticket_list {
+                    sku.price += screen.express_fee;
+                }
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+/// Buyers (实名购票人) of the account behind `client`.
+pub async fn fetch_buyers(client: &Client, endpoints: &Endpoints, project_id: String) -> Result<ApiResponse<BuyerList>> {
+    ApiResponse::parse(get_json(client, &endpoints.buyer_list(&project_id)).await?, "buyer/list")
+}
+
+/// Profile of the account behind `client`, which should come from
+/// [`ClientPool::for_cookies`] so it carries that account's cookies.
+pub async fn fetch_user_info(client: &Client, endpoints: &Endpoints) -> Result<ApiResponse<UserNav>> {
+    ApiResponse::parse(get_json(client, &endpoints.user_nav()).await?, "x/web-interface/nav")
+}
+
+/// Fetch the profile behind `cookies` and build an [`Account`] from it.
+pub async fn fetch_account(pool: &ClientPool, endpoints: &Endpoints, cookies: Vec<String>) -> Result<Account> {
+    // Fetch user info to get uid, name, face
+    let client = pool.for_cookies(&cookies)?;
+    let res = fetch_user_info(&client, endpoints).await?;
+
+    if !res.is_ok() {
+        return Err(Error::Auth { code: Some(res.status()), message: format!("Invalid cookies: {}", res.message()) });
+    }
+    let nav = res.into_data()?;
+
+    Ok(Account {
+        uid: nav.mid.to_string(),
+        name: nav.uname,
+        face: nav.face,
+        cookies,
+        level: nav.level_info.current_level as i32,
+        is_vip: nav.vip_status == 1,
+        coins: nav.money,
+    })
+}
+
+/// Delivery addresses of the account behind `client`.
+pub async fn fetch_address_list(client: &Client, endpoints: &Endpoints) -> Result<ApiResponse<AddressList>> {
+    ApiResponse::parse(get_json(client, &endpoints.address_list()).await?, "addr/list")
+}
+
+/// Sync `clock` against the first of `sources` that answers, taking
+/// `samples` samples, see [`Clock::sync_with`]. Returns `{"diff", "server",
+/// "local", "uncertainty", "rtt"}` in milliseconds along with `samples`,
+/// `discarded` and the `source` URL used.
+pub async fn sync_time(clock: &Clock, sources: &[TimeSource], samples: u32) -> Result<Value> {
+    let sync = clock.sync_with(sources, samples).await?;
+
+    let local_time = get_local_time();
+    let diff = sync.offset_ms.round() as i64;
+
+    Ok(serde_json::json!({
+        "diff": diff,
+        "server": local_time + diff,
+        "local": local_time,
+        "uncertainty": sync.uncertainty_ms,
+        "rtt": sync.best_rtt_ms,
+        "samples": sync.samples,
+        "discarded": sync.discarded,
+        "source": sync.source
+    }))
+}
+
+pub fn get_local_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
@@ -1,13 +1,12 @@
 use reqwest::Client;
 use serde_json::Value;
 use std::time::Duration;
-use anyhow::{Result, anyhow};
+use crate::error::{Error, Result};
+use crate::config::Endpoints;
 
-pub async fn generate_qrcode() -> Result<(String, String)> {
-    let client = Client::new();
-    let url = "https://passport.bilibili.com/x/passport-login/web/qrcode/generate";
-    let res: Value = client.get(url)
-        .header("User-Agent", "Mozilla/5.0")
+pub async fn generate_qrcode(client: &Client, endpoints: &Endpoints) -> Result<(String, String)> {
+    let url = endpoints.qrcode_generate();
+    let res: Value = client.get(&url)
         .send()
         .await?
         .json()
@@ -18,19 +17,19 @@ pub async fn generate_qrcode() -> Result<(String, String)> {
         let qrcode_key = res["data"]["qrcode_key"].as_str().unwrap().to_string();
         Ok((url, qrcode_key))
     } else {
-        Err(anyhow!("Failed to generate QR code"))
+        Err(Error::api(res["code"].as_i64().unwrap_or(-1), format!("Failed to generate QR code: {}", res["message"].as_str().unwrap_or_default())))
     }
 }
 
-pub async fn poll_login(qrcode_key: &str) -> Result<String> {
-    let client = Client::new();
-    let url = "https://passport.bilibili.com/x/passport-login/web/qrcode/poll";
+/// Poll until the QR code is confirmed and return the login Set-Cookie
+/// lines as a JSON array. `client` must not have a cookie store.
+pub async fn poll_login(client: &Client, endpoints: &Endpoints, qrcode_key: &str) -> Result<String> {
+    let url = endpoints.qrcode_poll();
 
     for _ in 0..120 {
-        let resp = client.get(url)
+        let resp = client.get(&url)
             .query(&[("qrcode_key", qrcode_key)])
-            .header("User-Agent", "Mozilla/5.0")
-            .send()
+                .send()
             .await?;
 
         let headers = resp.headers().clone();
@@ -52,10 +51,10 @@ pub async fn poll_login(qrcode_key: &str) -> Result<String> {
                 tokio::time::sleep(Duration::from_millis(1000)).await;
                 continue;
             } else {
-                return Err(anyhow!("Login failed: {}", res_json["data"]["message"]));
+                return Err(Error::Auth { code: Some(code), message: format!("Login failed: {}", res_json["data"]["message"]) });
             }
         }
         tokio::time::sleep(Duration::from_millis(1000)).await;
     }
-    Err(anyhow!("Login timeout"))
+    Err(Error::Auth { code: None, message: "Login timeout".to_string() })
 }
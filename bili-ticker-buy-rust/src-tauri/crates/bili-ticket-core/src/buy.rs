@@ -0,0 +1,1054 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use reqwest::Client;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use crate::util::CTokenGenerator;
+use crate::phase::{Outcome, Phase, PhaseMachine};
+use crate::task::{now_string, TaskHandle, TaskRecord};
+use crate::storage::{self, HistoryItem};
+use crate::api; // Import api module
+use crate::clock::Clock;
+use crate::timesync::TimeSource;
+use crate::config::{Endpoints, Settings};
+use crate::event::{EventSink, FinishReason, TaskEvent};
+use crate::logs::{LogEntry, LogLevel};
+use crate::error::{Error, Result};
+use serde_json::json;
+use chrono::Local;
+
+/// Error code dictionary from the original Python project
+/// Maps error codes to human-readable messages
+fn get_error_message(errno: i64) -> &'static str {
+    match errno {
+        0 => "成功",
+        3 => "抢票CD中",
+        100001 => "无票",
+        100003 => "验证码过期",
+        100009 => "库存不足,暂无余票",
+        100016 => "项目不可售",
+        100017 => "票种不可售",
+        100034 => "票价错误",
+        100039 => "活动收摊啦,下次要快点哦",
+        100041 => "对未发售的票进行抢票",
+        100048 => "已经下单，有尚未完成订单",
+        100051 => "订单准备过期，重新验证",
+        900001 => "当前拥挤，请稍后再试",
+        900002 => "当前拥挤，请稍后再试",
+        _ => "未知错误码",
+    }
+}
+
+/// Errnos after which a task moves on to its next fallback SKU: sold out
+/// (100009) and not sellable (100017).
+pub const FALLBACK_ERRNOS: [i64; 2] = [100009, 100017];
+
+/// A screen and SKU to try when the current one is sold out, see
+/// [`TicketInfo::fallbacks`].
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SkuChoice {
+    pub screen_id: String,
+    #[serde(default)]
+    pub screen_name: Option<String>,
+    pub sku_id: String,
+    #[serde(default)]
+    pub sku_name: Option<String>,
+    /// Price of one ticket in cents
+    pub price: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TicketInfo {
+    pub project_id: String,
+    pub project_name: Option<String>,
+    pub screen_id: String,
+    #[serde(default)]
+    pub screen_name: Option<String>,
+    pub sku_id: String,
+    #[serde(default)]
+    pub sku_name: Option<String>,
+    pub count: u32,
+    pub buyer_info: serde_json::Value,
+    pub deliver_info: serde_json::Value,
+    #[serde(default)]
+    pub cookies: Vec<String>, 
+    pub is_hot_project: Option<bool>,
+    pub pay_money: Option<u32>,
+    pub contact_name: Option<String>,
+    pub contact_tel: Option<String>,
+    /// Tried in order after `screen_id`/`sku_id` on one of
+    /// [`FALLBACK_ERRNOS`]. The last SKU reached is kept for the retries.
+    #[serde(default)]
+    pub fallbacks: Vec<SkuChoice>,
+}
+
+impl TicketInfo {
+    /// Move to the next fallback SKU, recomputing `pay_money` for `count`
+    /// tickets. Returns `None` when there is none left.
+    pub fn next_fallback(&mut self) -> Option<SkuChoice> {
+        if self.fallbacks.is_empty() {
+            return None;
+        }
+        let next = self.fallbacks.remove(0);
+        self.screen_id = next.screen_id.clone();
+        self.screen_name = next.screen_name.clone();
+        self.sku_id = next.sku_id.clone();
+        self.sku_name = next.sku_name.clone();
+        self.pay_money = Some(next.price * self.count.max(1));
+        Some(next)
+    }
+
+    /// Replace `buyer_info` with buyers picked in the UI, filling missing
+    /// contact name/tel from the first buyer.
+    pub fn apply_buyers(&mut self, buyers: Vec<serde_json::Value>) {
+        if buyers.is_empty() {
+            return;
+        }
+        self.buyer_info = serde_json::Value::Array(buyers.clone());
+
+        // Ensure contact info is present and not empty
+        let contact_name_missing = self.contact_name.as_ref().map(|s| s.is_empty()).unwrap_or(true);
+        let contact_tel_missing = self.contact_tel.as_ref().map(|s| s.is_empty()).unwrap_or(true);
+
+        if contact_name_missing || contact_tel_missing {
+            if let Some(first) = buyers.first() {
+                if contact_name_missing {
+                    if let Some(name) = first["name"].as_str() {
+                        if !name.is_empty() {
+                            self.contact_name = Some(name.to_string());
+                        }
+                    }
+                }
+                if contact_tel_missing {
+                    // Try different fields for phone
+                    let tel = first["tel"].as_str()
+                        .or(first["mobile"].as_str())
+                        .or(first["phone"].as_str());
+
+                    if let Some(t) = tel {
+                        if !t.is_empty() && !t.contains('*') {
+                            self.contact_tel = Some(t.to_string());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// How a buy task runs. Start from [`BuyOptions::from_settings`] and
+/// override what the caller specified.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BuyOptions {
+    /// Delay between create attempts in ms
+    pub interval: u64,
+    /// 0: retry until stopped, 1: stop after `total_attempts` rounds
+    pub mode: u32,
+    pub total_attempts: u32,
+    /// Local start time, `%Y-%m-%d %H:%M:%S`
+    pub time_start: Option<String>,
+    pub proxy: Option<String>,
+    /// Server minus local time in ms, used until the shared [`Clock`] has
+    /// been synced
+    pub time_offset: Option<f64>,
+    /// Where front ends sync their [`Clock`] from, see
+    /// [`timesync::sources`](crate::timesync::sources). Not read by the task.
+    pub ntp_server: Option<String>,
+    /// Tried before `ntp_server`
+    #[serde(default)]
+    pub time_sources: Vec<TimeSource>,
+    /// Stop sibling tasks once this one orders, see [`TaskRecord::group`](crate::task::TaskRecord::group)
+    #[serde(default)]
+    pub exclusive: bool,
+    /// Poll the project and only prepare while the SKU can be bought
+    #[serde(default)]
+    pub watch: bool,
+    /// Delay between polls in watch mode in ms
+    #[serde(default = "default_watch_interval")]
+    pub watch_interval: u64,
+    /// `time_start` was taken from the SKU's published sale start, see
+    /// [`sale_start_time`]. It is checked again shortly before firing.
+    #[serde(default)]
+    pub at_sale_start: bool,
+    /// Local time to give up at, `%Y-%m-%d %H:%M:%S`
+    #[serde(default)]
+    pub time_end: Option<String>,
+    /// Give up after this many failed prepares in a row, 0 for no limit
+    #[serde(default)]
+    pub max_prepare_failures: u32,
+    /// Give up after this many prepare and create requests, 0 for no limit
+    #[serde(default)]
+    pub max_requests: u32,
+    /// Give up on these errnos from prepare or create
+    #[serde(default = "default_stop_errnos")]
+    pub stop_errnos: Vec<i64>,
+}
+
+fn default_watch_interval() -> u64 {
+    crate::config::BuyDefaults::default().watch_interval
+}
+
+fn default_stop_errnos() -> Vec<i64> {
+    crate::config::BuyDefaults::default().stop_errnos
+}
+
+impl BuyOptions {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            interval: settings.buy.interval,
+            mode: settings.buy.mode,
+            total_attempts: settings.buy.total_attempts,
+            time_start: None,
+            proxy: settings.proxy.clone().filter(|p| !p.trim().is_empty()),
+            time_offset: None,
+            ntp_server: settings.ntp_server.clone().filter(|s| !s.trim().is_empty()),
+            time_sources: settings.time_sources.clone(),
+            exclusive: settings.buy.exclusive,
+            watch: false,
+            watch_interval: settings.buy.watch_interval,
+            at_sale_start: false,
+            time_end: None,
+            max_prepare_failures: settings.buy.max_prepare_failures,
+            max_requests: settings.buy.max_requests,
+            stop_errnos: settings.buy.stop_errnos.clone(),
+        }
+    }
+}
+
+/// When a task gives up on its own, besides running out of rounds in
+/// finite mode. Checked before each request; the errnos after each reply.
+struct StopPolicy {
+    time_end: Option<chrono::DateTime<Local>>,
+    max_prepare_failures: u32,
+    max_requests: u32,
+    stop_errnos: Vec<i64>,
+}
+
+impl StopPolicy {
+    /// `prepare_failures` counts failed prepares since the last success.
+    fn limit_reached(&self, record: &TaskRecord, prepare_failures: u32) -> Option<(FinishReason, String)> {
+        if let Some(reason) = self.deadline_passed() {
+            return Some(reason);
+        }
+        if self.max_prepare_failures > 0 && prepare_failures >= self.max_prepare_failures {
+            return Some((FinishReason::PrepareFailures, format!("连续 {} 次准备订单失败，任务停止", prepare_failures)));
+        }
+        let requests = record.prepares + record.attempts;
+        if self.max_requests > 0 && requests >= self.max_requests {
+            return Some((FinishReason::RequestLimit, format!("已发送 {} 次请求，达到上限，任务停止", requests)));
+        }
+        None
+    }
+
+    fn deadline_passed(&self) -> Option<(FinishReason, String)> {
+        let end = self.time_end.filter(|end| Local::now() >= *end)?;
+        Some((FinishReason::DeadlineReached, format!("已到结束时间 {}，任务停止", end.format("%Y-%m-%d %H:%M:%S"))))
+    }
+
+    /// Time left until `time_end`, if there is one.
+    fn until_deadline(&self) -> Option<Duration> {
+        self.time_end.map(|end| (end - Local::now()).to_std().unwrap_or_default())
+    }
+
+    fn terminal(&self, errno: i64) -> Option<(FinishReason, String)> {
+        self.stop_errnos.contains(&errno)
+            .then(|| (FinishReason::TerminalErrno, format!("{} ({})，任务停止", errno, get_error_message(errno))))
+    }
+}
+
+/// How long before the sale start the published time is checked again.
+const SALE_RECHECK_BEFORE: Duration = Duration::from_secs(60);
+
+/// Published sale start of the task's SKU from `project/getV2`.
+pub async fn sale_start_time(client: &Client, endpoints: &Endpoints, info: &TicketInfo) -> Result<chrono::DateTime<Local>> {
+    let project = api::fetch_project_info(client, endpoints, info.project_id.clone()).await?.into_data()?;
+    project.sale_start(&info.screen_id, &info.sku_id).ok_or_else(|| {
+        Error::NotFound(format!("No sale start published for SKU {} of screen {}", info.sku_id, info.screen_id))
+    })
+}
+
+/// Schedule `options` for the SKU's sale start when `at_sale_start` is
+/// set. A sale that already started leaves the task to start right away.
+pub async fn apply_sale_start(client: &Client, endpoints: &Endpoints, info: &TicketInfo, options: &mut BuyOptions) -> Result<()> {
+    if !options.at_sale_start {
+        return Ok(());
+    }
+    let sale_start = sale_start_time(client, endpoints, info).await?;
+    options.time_start = (sale_start > Local::now()).then(|| sale_start.format("%Y-%m-%d %H:%M:%S").to_string());
+    Ok(())
+}
+
+#[derive(Clone, Serialize)]
+struct LogPayload {
+    task_id: String,
+    #[serde(flatten)]
+    entry: LogEntry,
+}
+
+fn emit<T: Serialize>(sink: &dyn EventSink, event: &str, payload: T) -> Result<()> {
+    sink.emit(event, serde_json::to_value(payload)?)
+}
+
+fn emit_log(sink: &dyn EventSink, task: &TaskHandle, level: LogLevel, message: &str) {
+    let entry = task.log(level, message);
+    let record = task.record();
+    log::log!(
+        log::Level::from(level),
+        "[task {} project={} sku={} phase={:?} attempts={}] {}",
+        task.id(), record.project_id, record.sku_id, entry.phase, record.attempts, message
+    );
+    let _ = emit(sink, "log", LogPayload {
+        task_id: task.id().to_string(),
+        entry,
+    });
+}
+
+pub(crate) fn parse_start_time(ts: &str) -> Option<chrono::DateTime<Local>> {
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"].iter()
+        .find_map(|fmt| chrono::NaiveDateTime::parse_from_str(ts, fmt).ok())
+        .and_then(|t| t.and_local_timezone(Local).single())
+}
+
+/// Sleep until `interval` has passed since `start`.
+async fn pace(start: Instant, interval: Duration) {
+    let elapsed = start.elapsed();
+    if elapsed < interval {
+        let remaining = interval - elapsed;
+        if remaining.as_millis() > 20 {
+            sleep(remaining - Duration::from_millis(10)).await;
+        }
+        // Yield with a small sleep rather than spinning
+        while start.elapsed() < interval {
+            sleep(Duration::from_millis(1)).await;
+        }
+    }
+}
+
+/// State of one running task carried between phases. The [`PhaseMachine`]
+/// decides where to go next; the methods here make the requests and report
+/// an [`Outcome`].
+struct BuyRun {
+    sink: Arc<dyn EventSink>,
+    client: Client,
+    endpoints: Endpoints,
+    clock: Clock,
+    /// Offset used until `clock` has been synced
+    fallback_offset: i64,
+    task: Arc<TaskHandle>,
+    task_id: String,
+    info: TicketInfo,
+    interval: Duration,
+    is_hot: bool,
+    ctoken_gen: CTokenGenerator,
+    /// Static device ID for this task
+    device_id: String,
+    machine: PhaseMachine,
+    /// Set by a successful prepare
+    ptoken: String,
+    create_payload: serde_json::Value,
+    order_id: Option<String>,
+    watch: bool,
+    watch_interval: Duration,
+    /// Clickable and sale flag of the last poll, to log only changes
+    watch_status: Option<(bool, Option<i64>)>,
+    /// Last known published sale start when scheduled by it
+    sale_start: Option<chrono::DateTime<Local>>,
+    stop_policy: StopPolicy,
+    /// Failed prepares since the last successful one
+    prepare_failures: u32,
+    /// Why the task gave up, set along with [`Outcome::GiveUp`]
+    give_up: Option<(FinishReason, String)>,
+}
+
+impl BuyRun {
+    fn log(&self, message: &str) {
+        self.log_at(LogLevel::Info, message);
+    }
+
+    fn log_at(&self, level: LogLevel, message: &str) {
+        emit_log(self.sink.as_ref(), &self.task, level, message);
+    }
+
+    fn emit_event(&self, event: TaskEvent) {
+        if let Err(e) = event.emit(self.sink.as_ref(), &self.task_id) {
+            self.log_at(LogLevel::Warn, &format!("Warning: Failed to emit {} event: {}", event.name(), e));
+        }
+    }
+
+    fn emit_phase(&self) {
+        self.emit_event(TaskEvent::TaskPhase {
+            phase: self.machine.phase(),
+            attempt: self.machine.attempt(),
+        });
+    }
+
+    fn advance(&mut self, outcome: Outcome) {
+        let before = self.machine.phase();
+        let after = self.machine.on(outcome);
+        self.task.update(|r| r.phase = after);
+        if before == Phase::Creating && after == Phase::Preparing {
+            self.log("Retry attempts exhausted or token expired. Restarting loop...");
+        }
+        if after != before {
+            self.emit_phase();
+        }
+    }
+
+    fn emit_paused(&self, paused: bool) {
+        self.emit_event(TaskEvent::TaskPaused { paused, phase: self.machine.phase() });
+    }
+
+    /// Hold until the task is resumed or stopped, keeping the phase and
+    /// everything prepared so far.
+    async fn wait_while_paused(&self) {
+        let mut paused = self.task.paused();
+        self.log(&format!("Paused in phase {:?}", self.machine.phase()));
+        self.emit_paused(true);
+        // Stopping clears the pause too, see [`TaskHandle::stop`]
+        while *paused.borrow_and_update() && !self.task.is_stopped() {
+            if paused.changed().await.is_err() {
+                break;
+            }
+        }
+        if !self.task.is_stopped() {
+            self.log("Resumed");
+            self.emit_paused(false);
+        }
+    }
+
+    /// Server minus local time in ms: the shared clock's offset, or the
+    /// task's own `time_offset` until the clock has been synced.
+    fn offset_ms(&self) -> i64 {
+        self.clock.offset_ms().unwrap_or(self.fallback_offset)
+    }
+
+    /// Wait for the task's start time, shifted by the server time offset
+    /// which the shared [`Clock`] keeps up to date while anyone waits. The
+    /// start time may be moved meanwhile, see [`TaskHandle::reschedule`].
+    /// A pause holds the task here; once resumed past its start time it
+    /// starts right away.
+    async fn wait_for_start(&mut self) -> Outcome {
+        let mut start_time = self.task.start_time();
+        let Some(mut target) = *start_time.borrow_and_update() else {
+            return Outcome::StartTimeReached;
+        };
+
+        // Subscribed for as long as this task waits, which keeps the clock refreshing
+        let mut clock = self.clock.subscribe();
+        let mut paused = self.task.paused();
+        let initial_offset = self.offset_ms();
+
+        self.log(&format!("Waiting until: {} (Initial Offset: {}ms)", target.format("%Y-%m-%d %H:%M:%S%.3f"), initial_offset));
+        self.emit_event(TaskEvent::TaskScheduled {
+            time_start: target.format("%Y-%m-%d %H:%M:%S").to_string(),
+            offset_ms: initial_offset,
+        });
+
+        let mut countdown_secs = None;
+        let mut sale_rechecked = false;
+        let outcome = loop {
+            if self.task.is_stopped() {
+                break Outcome::Stop;
+            }
+            if self.task.is_paused() {
+                self.wait_while_paused().await;
+                continue;
+            }
+            // An end time before the start time ends the task without firing
+            if let Some(reason) = self.stop_policy.deadline_passed() {
+                self.give_up = Some(reason);
+                break Outcome::GiveUp;
+            }
+
+            let offset_val = self.offset_ms();
+            let target_with_offset = target - chrono::Duration::milliseconds(offset_val);
+            let remaining_ms = (target_with_offset - Local::now()).num_milliseconds();
+
+            if remaining_ms <= 0 {
+                break Outcome::StartTimeReached;
+            }
+            if countdown_secs != Some(remaining_ms / 1000) {
+                countdown_secs = Some(remaining_ms / 1000);
+                self.emit_event(TaskEvent::Countdown { remaining_ms, offset_ms: offset_val });
+            }
+            if self.sale_start.is_some() && !sale_rechecked && remaining_ms <= SALE_RECHECK_BEFORE.as_millis() as i64 {
+                sale_rechecked = true;
+                self.recheck_sale_start().await;
+                continue;
+            }
+
+            // Adaptive sleep strategy for high precision
+            let step = if remaining_ms > 5000 {
+                Duration::from_secs(1)
+            } else if remaining_ms > 1000 {
+                Duration::from_millis(100)
+            } else if remaining_ms > 50 {
+                Duration::from_millis(10)
+            } else {
+                // Use tokio::time::sleep for the last 50ms — avoids CPU burn
+                // while still yielding to the async runtime for precise timing
+                Duration::from_millis(1)
+            };
+            let step = self.stop_policy.until_deadline().map_or(step, |left| step.min(left));
+            // Wake up early when the start time is moved
+            tokio::select! {
+                _ = sleep(step) => {}
+                Ok(()) = start_time.changed() => {
+                    if let Some(new_target) = *start_time.borrow_and_update() {
+                        target = new_target;
+                        sale_rechecked = false;
+                        let time_start = target.format("%Y-%m-%d %H:%M:%S").to_string();
+                        self.log(&format!("Rescheduled to: {}", time_start));
+                        self.emit_event(TaskEvent::TaskRescheduled { time_start });
+                    }
+                }
+                Ok(()) = paused.changed() => {}
+                Ok(()) = clock.changed() => {
+                    if let Some(offset) = clock.borrow_and_update().offset_ms {
+                        self.log_at(LogLevel::Debug, &format!("Clock offset now {}ms", offset));
+                    }
+                }
+            }
+        };
+
+        if outcome == Outcome::StartTimeReached {
+            self.log("Time reached! Starting execution...");
+        }
+        outcome
+    }
+
+    /// Poll the project once in watch mode, then wait out `watch_interval`
+    /// unless the SKU can be bought.
+    async fn watch(&mut self) -> Outcome {
+        let start = Instant::now();
+        let sku_label = self.info.sku_name.clone().unwrap_or_else(|| self.info.sku_id.clone());
+        let project = api::fetch_project_info(&self.client, &self.endpoints, self.info.project_id.clone()).await
+            .and_then(|res| res.into_data());
+        match project {
+            Ok(project) => match project.sku(&self.info.screen_id, &self.info.sku_id) {
+                Some(sku) if sku.purchasable() => {
+                    self.log(&format!("{} can be bought (sale flag {:?}). Preparing...", sku_label, sku.sale_flag()));
+                    self.watch_status = None;
+                    self.emit_event(TaskEvent::Restocked {
+                        screen_id: self.info.screen_id.clone(),
+                        sku_id: self.info.sku_id.clone(),
+                        sale_flag: sku.sale_flag(),
+                    });
+                    return Outcome::Restocked;
+                }
+                Some(sku) => {
+                    let status = (sku.clickable(), sku.sale_flag());
+                    let level = if self.watch_status == Some(status) { LogLevel::Debug } else { LogLevel::Info };
+                    self.log_at(level, &format!("Watching {}: clickable {}, sale flag {:?}", sku_label, status.0, status.1));
+                    self.watch_status = Some(status);
+                }
+                None => self.log_at(LogLevel::Warn, &format!("SKU {} of screen {} not found in project", self.info.sku_id, self.info.screen_id)),
+            },
+            Err(e) => self.log_at(LogLevel::Warn, &format!("Watch request failed: {}", e)),
+        }
+
+        // Short steps, so a stop or pause is seen before the next poll
+        while start.elapsed() < self.watch_interval && !self.task.is_stopped() && !self.task.is_paused() {
+            sleep((self.watch_interval - start.elapsed()).min(Duration::from_millis(200))).await;
+        }
+        Outcome::NotOnSale
+    }
+
+    /// Look up the published sale start again and follow it if Bilibili
+    /// moved the sale. A start time moved by the user is left alone.
+    async fn recheck_sale_start(&mut self) {
+        let Some(known) = self.sale_start else { return };
+        match sale_start_time(&self.client, &self.endpoints, &self.info).await {
+            Ok(sale_start) if sale_start != known => {
+                let time_start = sale_start.format("%Y-%m-%d %H:%M:%S").to_string();
+                self.log(&format!("Sale start moved from {} to {}", known.format("%Y-%m-%d %H:%M:%S"), time_start));
+                self.sale_start = Some(sale_start);
+                if self.task.record().rescheduled {
+                    self.log("Keeping the start time set by the user");
+                } else if let Err(e) = self.task.follow_sale_start(&time_start) {
+                    self.log_at(LogLevel::Warn, &format!("Failed to follow the new sale start: {}", e));
+                }
+            }
+            Ok(_) => self.log("Sale start unchanged"),
+            Err(e) => self.log_at(LogLevel::Warn, &format!("Failed to re-check the sale start: {}", e)),
+        }
+    }
+
+    async fn prepare(&mut self) -> Outcome {
+        self.log("1) Preparing order...");
+
+        let mut token_payload = json!({
+            "count": self.info.count,
+            "screen_id": self.info.screen_id,
+            "order_type": 1,
+            "project_id": self.info.project_id,
+            "sku_id": self.info.sku_id,
+            "token": "",
+            "newRisk": true,
+        });
+        if self.is_hot {
+            token_payload["token"] = json!(self.ctoken_gen.generate_ctoken(false));
+        }
+
+        self.task.update(|r| r.prepares += 1);
+        let prepare_url = self.endpoints.order_prepare(&self.info.project_id);
+        let start = Instant::now();
+        let res_json: serde_json::Value = match self.client.post(&prepare_url).json(&token_payload).send().await {
+            Ok(res) => res.json().await.unwrap_or(json!({})),
+            Err(e) => {
+                self.log_at(LogLevel::Warn, &format!("Prepare request error: {}", e));
+                self.emit_event(TaskEvent::PrepareResult {
+                    errno: None,
+                    latency_ms: start.elapsed().as_millis() as u64,
+                    message: e.to_string(),
+                });
+                self.prepare_failures += 1;
+                sleep(self.interval).await;
+                return Outcome::PrepareFailed;
+            }
+        };
+        self.log_at(LogLevel::Debug, &format!("Prepare result: {:?}", res_json));
+        self.emit_event(TaskEvent::PrepareResult {
+            errno: res_json["errno"].as_i64().or(res_json["code"].as_i64()),
+            latency_ms: start.elapsed().as_millis() as u64,
+            message: res_json["msg"].as_str().or(res_json["message"].as_str()).unwrap_or_default().to_string(),
+        });
+
+        if res_json["errno"].as_i64().unwrap_or(-1) != 0 && res_json["code"].as_i64().unwrap_or(-1) != 0 {
+            self.log_at(LogLevel::Warn, &format!("Prepare failed: {:?}", res_json));
+            let errno = res_json["errno"].as_i64().or(res_json["code"].as_i64()).unwrap_or(-1);
+            if self.switch_sku(errno) {
+                self.prepare_failures = 0;
+                return Outcome::SkuSwitched;
+            }
+            if let Some(reason) = self.stop_policy.terminal(errno) {
+                self.give_up = Some(reason);
+                return Outcome::GiveUp;
+            }
+            if self.watch && FALLBACK_ERRNOS.contains(&errno) {
+                self.log("Sold out. Back to watching...");
+                return Outcome::SoldOut;
+            }
+            self.prepare_failures += 1;
+            sleep(self.interval).await;
+            return Outcome::PrepareFailed;
+        }
+        self.prepare_failures = 0;
+
+        let token = res_json["data"]["token"].as_str().unwrap_or("").to_string();
+        self.ptoken = res_json["data"]["ptoken"].as_str().unwrap_or("").to_string();
+        self.create_payload = self.build_create_payload(&token);
+
+        // Debug log for payload details
+        self.log_at(LogLevel::Debug, &format!("Payload - Count: {}, Buyers: {}", self.create_payload["count"], self.create_payload["buyer_info"]));
+        self.log_at(LogLevel::Debug, &format!("Contact Info - Name: {:?}, Tel: {:?}", self.create_payload.get("contact_name"), self.create_payload.get("contact_tel")));
+        Outcome::Prepared
+    }
+
+    fn build_create_payload(&self, token: &str) -> serde_json::Value {
+        let info = &self.info;
+        let now_ms = Local::now().timestamp_millis() as u64;
+        let click_origin = now_ms - rand::random::<u64>() % 2000 - 1000; // 1-3 seconds ago
+
+        let mut create_payload = json!({
+            "project_id": info.project_id,
+            "screen_id": info.screen_id,
+            "sku_id": info.sku_id,
+            "count": info.count,
+            "order_type": 1,
+            "buyer_info": info.buyer_info.to_string(),
+            "deliver_info": info.deliver_info.to_string(),
+            "token": token,
+            "again": 1,
+            "timestamp": now_ms,
+            "deviceId": self.device_id,
+            "requestSource": "neul-next",
+            "newRisk": true,
+            "clickPosition": {
+                "x": rand::random::<u64>() % 500 + 100,
+                "y": rand::random::<u64>() % 1000 + 500,
+                "origin": click_origin,
+                "now": now_ms
+            }
+        });
+
+        if let Some(pay_money) = info.pay_money {
+            create_payload["pay_money"] = json!(pay_money);
+        }
+
+        // Add contact info
+        if let Some(name) = &info.contact_name {
+            create_payload["contact_name"] = json!(name);
+            create_payload["buyer"] = json!(name);
+        }
+        if let Some(tel) = &info.contact_tel {
+            if !tel.contains('*') {
+                create_payload["contact_tel"] = json!(tel);
+                create_payload["tel"] = json!(tel);
+            }
+        }
+        create_payload
+    }
+
+    async fn create(&mut self) -> Outcome {
+        if self.machine.attempt() == 0 {
+            self.log("2) Creating order...");
+        }
+        let attempt = self.machine.attempt() + 1;
+        let max_attempts = self.machine.attempts_per_round();
+
+        let mut create_url = self.endpoints.order_create(&self.info.project_id);
+        if self.is_hot {
+            self.ctoken_gen.set_time_offset(self.offset_ms() / 1000);
+            self.create_payload["ctoken"] = json!(self.ctoken_gen.generate_ctoken(true));
+            self.create_payload["ptoken"] = json!(self.ptoken);
+            self.create_payload["orderCreateUrl"] = json!(self.endpoints.order_create_base());
+            create_url.push_str(&format!("&ptoken={}", self.ptoken));
+        }
+
+        self.task.update(|r| r.attempts += 1);
+        let total_attempt = self.task.record().attempts;
+        let start = Instant::now();
+        let creating = self.task.begin_create();
+        let res = self.client.post(&create_url)
+            .json(&self.create_payload)
+            .send()
+            .await;
+        let res = match res {
+            Ok(r) => Ok(r.json::<serde_json::Value>().await.unwrap_or(json!({}))),
+            Err(e) => Err(e),
+        };
+        drop(creating);
+
+        match res {
+            Ok(r_json) => {
+                let errno = r_json["errno"].as_i64().or(r_json["code"].as_i64()).unwrap_or(-1);
+                self.task.update(|r| r.last_errno = Some(errno));
+
+                self.log(&format!("[Attempt {}/{}] Code: {} ({}) | Msg: {}", attempt, max_attempts, errno, get_error_message(errno), r_json["msg"]));
+                self.emit_event(TaskEvent::AttemptResult {
+                    attempt: total_attempt,
+                    round_attempt: attempt,
+                    round_size: max_attempts,
+                    errno: Some(errno),
+                    latency_ms: start.elapsed().as_millis() as u64,
+                    message: get_error_message(errno).to_string(),
+                });
+
+                if errno == 0 || errno == 100048 || errno == 100079 {
+                    self.log("Order created successfully!");
+                    let siblings = self.task.stop_siblings();
+                    if !siblings.is_empty() {
+                        self.log(&format!("Stopped tasks for the same buyers: {}", siblings.join(", ")));
+                    }
+                    if errno == 0 {
+                        let order_id = &r_json["data"]["orderId"];
+                        let order_id = order_id.as_str().map(|s| s.to_string())
+                            .or_else(|| order_id.as_i64().map(|n| n.to_string()))
+                            .filter(|id| !id.is_empty());
+                        match &order_id {
+                            Some(id) => self.log(&format!("Order ID: {}", id)),
+                            None => self.log_at(LogLevel::Warn, &format!("Failed to extract Order ID from: {:?}", r_json)),
+                        }
+                        self.order_id = order_id;
+                    }
+                    self.emit_event(TaskEvent::OrderCreated {
+                        order_id: self.order_id.clone(),
+                        price: self.info.pay_money,
+                    });
+                    return Outcome::Created { has_order: self.order_id.is_some() };
+                }
+
+                if errno == 100034 {
+                    // Price changed
+                    if let Some(new_price) = r_json["data"]["pay_money"].as_u64() {
+                        self.log(&format!("Price updated to: {}", new_price));
+                        self.info.pay_money = Some(new_price as u32);
+                        self.create_payload["pay_money"] = json!(new_price);
+                    }
+                }
+
+                if errno == 100051 {
+                    return Outcome::TokenExpired;
+                }
+                if self.switch_sku(errno) {
+                    return Outcome::SkuSwitched;
+                }
+                if let Some(reason) = self.stop_policy.terminal(errno) {
+                    self.give_up = Some(reason);
+                    return Outcome::GiveUp;
+                }
+                if self.watch && FALLBACK_ERRNOS.contains(&errno) {
+                    self.log("Sold out. Back to watching...");
+                    return Outcome::SoldOut;
+                }
+            },
+            Err(e) => {
+                self.log_at(LogLevel::Warn, &format!("[Attempt {}/{}] Request error: {}", attempt, max_attempts, e));
+                self.emit_event(TaskEvent::AttemptResult {
+                    attempt: total_attempt,
+                    round_attempt: attempt,
+                    round_size: max_attempts,
+                    errno: None,
+                    latency_ms: start.elapsed().as_millis() as u64,
+                    message: e.to_string(),
+                });
+            }
+        }
+
+        // Precise sleep — use tokio::sleep to avoid CPU burn
+        pace(start, self.interval).await;
+        Outcome::CreateFailed
+    }
+
+    /// Move to the next fallback SKU when `errno` says the current one can
+    /// not be bought. The caller prepares again, as the token is per SKU.
+    fn switch_sku(&mut self, errno: i64) -> bool {
+        if !FALLBACK_ERRNOS.contains(&errno) {
+            return false;
+        }
+        let from = self.info.sku_name.clone().unwrap_or_else(|| self.info.sku_id.clone());
+        let Some(next) = self.info.next_fallback() else {
+            return false;
+        };
+        self.log(&format!(
+            "{} ({}) for {}, switching to {} {} (sku {}), pay_money {}",
+            errno, get_error_message(errno), from,
+            next.screen_name.as_deref().unwrap_or(&next.screen_id),
+            next.sku_name.as_deref().unwrap_or_default(),
+            next.sku_id,
+            self.info.pay_money.unwrap_or(0),
+        ));
+        self.task.update(|r| {
+            r.screen_id = next.screen_id.clone();
+            r.screen_name = next.screen_name.clone();
+            r.sku_id = next.sku_id.clone();
+            r.sku_name = next.sku_name.clone();
+        });
+        self.emit_event(TaskEvent::SkuSwitched {
+            errno,
+            screen_id: next.screen_id,
+            sku_id: next.sku_id,
+            sku_name: next.sku_name,
+            pay_money: self.info.pay_money.unwrap_or(0),
+        });
+        true
+    }
+
+    /// Fetch the payment QR code and record the order in history, whether
+    /// or not a payment URL was returned.
+    async fn fetch_payment(&mut self, base_dir: &std::path::Path) -> Outcome {
+        let Some(order_id) = self.order_id.clone() else {
+            return Outcome::PaymentFailed;
+        };
+
+        let mut pay_url = None;
+        let pay_url_api = self.endpoints.pay_param(&order_id);
+        match self.client.get(&pay_url_api).send().await {
+            Ok(pay_res) => {
+                if let Ok(pay_json) = pay_res.json::<serde_json::Value>().await {
+                    if let Some(code_url) = pay_json["data"]["code_url"].as_str() {
+                        pay_url = Some(code_url.to_string());
+                        self.emit_event(TaskEvent::PaymentReady {
+                            order_id: order_id.clone(),
+                            url: code_url.to_string(),
+                            price: self.info.pay_money,
+                        });
+                    } else {
+                        self.log_at(LogLevel::Warn, &format!("Failed to get payment URL: {:?}", pay_json));
+                    }
+                }
+            }
+            Err(e) => self.log_at(LogLevel::Warn, &format!("Failed to get payment URL: {}", e)),
+        }
+
+        let history_item = HistoryItem {
+            order_id,
+            project_name: self.info.project_name.clone().unwrap_or(self.info.project_id.clone()),
+            price: self.info.pay_money.unwrap_or(0),
+            time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            pay_url: pay_url.clone().unwrap_or_default(),
+        };
+        if let Err(e) = storage::add_history_item(base_dir, history_item) {
+            self.log_at(LogLevel::Warn, &format!("Warning: Failed to save history: {}", e));
+        }
+
+        if pay_url.is_some() { Outcome::PaymentReady } else { Outcome::PaymentFailed }
+    }
+
+    /// Report how the task ended.
+    fn finish(&self) {
+        let (reason, message) = match self.machine.phase() {
+            Phase::Done => (
+                FinishReason::OrderCreated,
+                format!("抢票成功！订单号: {}", self.order_id.as_deref().unwrap_or("未知")),
+            ),
+            Phase::Failed => match &self.give_up {
+                Some((reason, message)) => {
+                    self.log(&format!("Giving up: {}", message));
+                    (*reason, message.clone())
+                }
+                None => {
+                    self.log("Total attempts reached. Stopping.");
+                    (FinishReason::AttemptsExhausted, "达到最大尝试次数，任务停止".to_string())
+                }
+            },
+            _ => match self.task.superseded_by() {
+                Some(winner) => {
+                    self.log(&format!("Task {} ordered for the same buyers. Stopping.", winner));
+                    (FinishReason::SiblingSucceeded, format!("同组任务 {} 已下单，任务停止", winner))
+                }
+                None => {
+                    self.log("Task stopped by user.");
+                    (FinishReason::Stopped, "任务已停止".to_string())
+                }
+            },
+        };
+        self.task.update(|r| {
+            r.finished_at = Some(now_string());
+            r.message = Some(message.clone());
+        });
+        self.emit_event(TaskEvent::TaskFinished {
+            reason,
+            success: reason == FinishReason::OrderCreated,
+            message,
+        });
+    }
+}
+
+/// Run one buy task until it succeeds, is stopped or runs out of attempts.
+/// `client` must carry the cookies of `info.cookies`, see
+/// [`ClientPool::for_cookies`](crate::http::ClientPool::for_cookies).
+///
+/// Progress is emitted as [`TaskEvent`]s, phase changes included (see
+/// [`crate::phase`]), and recorded in `task` along with the request counters.
+/// The server time offset comes from `clock`, shared with the other tasks.
+#[allow(clippy::too_many_arguments)]
+pub async fn start_buy_task(
+    sink: Arc<dyn EventSink>, 
+    client: Client,
+    endpoints: Endpoints,
+    clock: Clock,
+    task: Arc<TaskHandle>,
+    info: TicketInfo, 
+    options: BuyOptions,
+    base_dir: std::path::PathBuf
+) -> Result<()> {
+    let BuyOptions {
+        interval, mode, total_attempts, time_start, proxy, time_offset, watch, watch_interval, at_sale_start,
+        time_end, max_prepare_failures, max_requests, stop_errnos, ..
+    } = options;
+    let time_start = time_start.filter(|s| !s.trim().is_empty());
+
+    let task_id = task.id().to_string();
+    emit_log(sink.as_ref(), &task, LogLevel::Info, "Starting buy task...");
+
+    let target_time = match &time_start {
+        Some(ts) => {
+            emit_log(sink.as_ref(), &task, LogLevel::Info, &format!("Scheduled start time: {}", ts));
+            let target = parse_start_time(ts);
+            if target.is_none() {
+                emit_log(sink.as_ref(), &task, LogLevel::Warn, "Invalid time format. Starting immediately.");
+            }
+            target
+        }
+        None => None,
+    };
+
+    if let Some(p) = &proxy {
+        emit_log(sink.as_ref(), &task, LogLevel::Info, &format!("Using proxy: {}", p));
+    }
+    let fallback_offset = time_offset.unwrap_or(0.0) as i64;
+    match clock.offset_ms() {
+        Some(offset) => emit_log(sink.as_ref(), &task, LogLevel::Info, &format!("Time offset: {}ms", offset)),
+        None if time_offset.is_some() => {
+            emit_log(sink.as_ref(), &task, LogLevel::Info, &format!("Time offset: {}ms until the clock is synced", fallback_offset));
+        }
+        None => {}
+    }
+    if watch {
+        emit_log(sink.as_ref(), &task, LogLevel::Info, &format!("Watch mode: polling the project every {}ms", watch_interval));
+    }
+    if at_sale_start && target_time.is_some() {
+        emit_log(sink.as_ref(), &task, LogLevel::Info, "Scheduled for the published sale start, checked again before firing");
+    }
+    let end_time = match time_end.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        Some(ts) => {
+            let end = parse_start_time(ts);
+            match end {
+                Some(_) => emit_log(sink.as_ref(), &task, LogLevel::Info, &format!("Giving up at: {}", ts)),
+                None => emit_log(sink.as_ref(), &task, LogLevel::Warn, "Invalid end time format. Running without one."),
+            }
+            end
+        }
+        None => None,
+    };
+
+    let device_id = format!("{:x}", md5::compute(format!("{}{}", task_id, rand::random::<u64>())));
+    // The ctoken counts seconds on the server's clock since the page opened
+    let offset_secs = clock.offset_ms().unwrap_or(fallback_offset) / 1000;
+    let mut run = BuyRun {
+        sink,
+        client,
+        endpoints,
+        clock,
+        fallback_offset,
+        task: task.clone(),
+        task_id,
+        is_hot: info.is_hot_project.unwrap_or(false),
+        info,
+        interval: Duration::from_millis(interval),
+        ctoken_gen: CTokenGenerator::new(
+            (std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64 + offset_secs) as u64,
+            offset_secs,
+            rand::random::<u64>() % 8000 + 2000
+        ),
+        device_id,
+        machine: PhaseMachine::new(target_time.is_some(), mode == 1, total_attempts).with_watch(watch),
+        ptoken: String::new(),
+        create_payload: serde_json::Value::Null,
+        order_id: None,
+        watch,
+        watch_interval: Duration::from_millis(watch_interval),
+        watch_status: None,
+        sale_start: target_time.filter(|_| at_sale_start),
+        stop_policy: StopPolicy { time_end: end_time, max_prepare_failures, max_requests, stop_errnos },
+        prepare_failures: 0,
+        give_up: None,
+    };
+    run.task.update(|r| r.phase = run.machine.phase());
+    run.emit_phase();
+
+    while !run.machine.phase().is_terminal() {
+        // An order that exists is still fetched and recorded after a stop
+        if task.is_stopped() && run.machine.phase() != Phase::FetchingPayment {
+            run.advance(Outcome::Stop);
+            break;
+        }
+        // Pausing holds back requests, not the payment of an order that exists
+        if task.is_paused() && run.machine.phase() != Phase::FetchingPayment {
+            run.wait_while_paused().await;
+            continue;
+        }
+        if run.machine.phase() != Phase::FetchingPayment {
+            if let Some(reason) = run.stop_policy.limit_reached(&task.record(), run.prepare_failures) {
+                run.give_up = Some(reason);
+                run.advance(Outcome::GiveUp);
+                continue;
+            }
+        }
+
+        let outcome = match run.machine.phase() {
+            Phase::Scheduled => run.wait_for_start().await,
+            Phase::Watching => run.watch().await,
+            Phase::Preparing => run.prepare().await,
+            Phase::Creating => run.create().await,
+            Phase::FetchingPayment => run.fetch_payment(&base_dir).await,
+            Phase::Done | Phase::Failed | Phase::Stopped => break,
+        };
+        run.advance(outcome);
+    }
+
+    run.finish();
+    Ok(())
+}
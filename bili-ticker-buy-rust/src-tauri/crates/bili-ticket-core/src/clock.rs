@@ -0,0 +1,141 @@
+//! App-wide server time offset shared by every task.
+//!
+//! A front end keeps one [`Clock`] and hands a clone to each buy task
+//! instead of each task syncing on its own. [`Clock::refresh`] re-syncs it
+//! every [`Settings::sync_interval`](crate::config::Settings::sync_interval),
+//! but only while someone is subscribed (a task waiting for its start
+//! time), so an idle app makes no time requests.
+
+use crate::error::Result;
+use crate::timesync::{self, TimeSource, TimeSync};
+use log::{debug, info, warn};
+use reqwest::Client;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::sleep;
+
+/// What a [`Clock`] publishes to its subscribers.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ClockState {
+    /// Server minus local time, `None` until first synced or set
+    pub offset_ms: Option<i64>,
+    /// The sync the offset comes from, `None` when set by hand
+    pub sync: Option<TimeSync>,
+}
+
+/// Shared handle to the server time offset. Cheap to clone; all clones see
+/// the same offset.
+#[derive(Clone)]
+pub struct Clock {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    client: Client,
+    sources: Mutex<Vec<TimeSource>>,
+    state: watch::Sender<ClockState>,
+    /// Period of [`Clock::refresh`], zero when it is off
+    refresh_interval: watch::Sender<Duration>,
+    /// Held while measuring, so overlapping syncs run one after another
+    syncing: tokio::sync::Mutex<()>,
+}
+
+impl Clock {
+    /// A clock that syncs against `sources` in order, see
+    /// [`timesync::sources`]. Nothing is measured until [`Clock::sync`], and
+    /// [`Clock::refresh`] is off until given an interval.
+    pub fn new(client: Client, sources: Vec<TimeSource>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                client,
+                sources: Mutex::new(sources),
+                state: watch::Sender::new(ClockState::default()),
+                refresh_interval: watch::Sender::new(Duration::ZERO),
+                syncing: tokio::sync::Mutex::new(()),
+            }),
+        }
+    }
+
+    /// Replace the sources used by [`Clock::sync`], e.g. after the settings
+    /// changed. The current offset is kept until the next sync.
+    pub fn set_sources(&self, sources: Vec<TimeSource>) {
+        *self.inner.sources.lock().unwrap() = sources;
+    }
+
+    /// How often [`Clock::refresh`] syncs, [`Duration::ZERO`] to only sync
+    /// on demand. Takes effect right away.
+    pub fn set_refresh_interval(&self, every: Duration) {
+        self.inner.refresh_interval.send_replace(every);
+    }
+
+    pub fn state(&self) -> ClockState {
+        self.inner.state.borrow().clone()
+    }
+
+    pub fn offset_ms(&self) -> Option<i64> {
+        self.inner.state.borrow().offset_ms
+    }
+
+    /// Follow offset changes. Holding a receiver also keeps
+    /// [`Clock::refresh`] syncing.
+    pub fn subscribe(&self) -> watch::Receiver<ClockState> {
+        self.inner.state.subscribe()
+    }
+
+    /// Use a known offset, e.g. one given on the command line.
+    pub fn set_offset(&self, offset_ms: i64) {
+        self.inner.state.send_replace(ClockState { offset_ms: Some(offset_ms), sync: None });
+    }
+
+    /// Measure against the configured sources and publish the result.
+    pub async fn sync(&self) -> Result<TimeSync> {
+        let sources = self.inner.sources.lock().unwrap().clone();
+        self.sync_with(&sources, timesync::DEFAULT_SAMPLES).await
+    }
+
+    /// Measure against `sources` instead of the configured ones, taking
+    /// `samples` samples, and publish the result.
+    pub async fn sync_with(&self, sources: &[TimeSource], samples: u32) -> Result<TimeSync> {
+        let _guard = self.inner.syncing.lock().await;
+        let sync = timesync::measure_first(&self.inner.client, sources, samples).await?;
+        let previous = self.inner.state.send_replace(ClockState {
+            offset_ms: Some(sync.offset_ms.round() as i64),
+            sync: Some(sync.clone()),
+        });
+        if previous.sync.map(|s| s.source) != Some(sync.source.clone()) {
+            info!("Clock synced with {}", sync.source);
+        }
+        debug!(
+            "Clock offset {:.0}ms ± {:.0}ms, best RTT {:.0}ms",
+            sync.offset_ms, sync.uncertainty_ms, sync.best_rtt_ms,
+        );
+        Ok(sync)
+    }
+
+    /// Re-sync every [refresh interval](Clock::set_refresh_interval) for as
+    /// long as the clock exists, skipping rounds in which nobody is
+    /// subscribed. Spawn this once on the front end's runtime.
+    pub async fn refresh(self) {
+        let mut interval = self.inner.refresh_interval.subscribe();
+        loop {
+            let every = *interval.borrow_and_update();
+            if every.is_zero() {
+                // The sender lives as long as `self`
+                let _ = interval.changed().await;
+                continue;
+            }
+            tokio::select! {
+                _ = sleep(every) => {}
+                _ = interval.changed() => continue,
+            }
+            if self.inner.state.receiver_count() == 0 {
+                continue;
+            }
+            if let Err(e) = self.sync().await {
+                warn!("Clock sync failed: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use crate::error::{Error, Result, StorageContext};
+use crate::logs::LogLevel;
+use crate::storage::atomic_write;
+use crate::timesync::TimeSource;
+
+pub const DEFAULT_SHOW_HOST: &str = "https://show.bilibili.com";
+pub const DEFAULT_API_HOST: &str = "https://api.bilibili.com";
+pub const DEFAULT_PASSPORT_HOST: &str = "https://passport.bilibili.com";
+
+/// Base URLs of the Bilibili hosts the app talks to.
+/// Any of them can be pointed at a staging or mock server.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Endpoints {
+    /// Ticket API: projects, buyers, addresses, orders
+    pub show: String,
+    /// Main site API: user info and server time
+    pub api: String,
+    /// QR code login
+    pub passport: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            show: DEFAULT_SHOW_HOST.to_string(),
+            api: DEFAULT_API_HOST.to_string(),
+            passport: DEFAULT_PASSPORT_HOST.to_string(),
+        }
+    }
+}
+
+fn join(host: &str, path: &str) -> String {
+    format!("{}{}", host.trim_end_matches('/'), path)
+}
+
+impl Endpoints {
+    /// Point every host at the same base URL, e.g. a local mock server.
+    pub fn all(base: &str) -> Self {
+        Self {
+            show: base.to_string(),
+            api: base.to_string(),
+            passport: base.to_string(),
+        }
+    }
+
+    pub fn project_info(&self, project_id: &str) -> String {
+        join(&self.show, &format!("/api/ticket/project/getV2?version=134&id={}&project_id={}", project_id, project_id))
+    }
+
+    pub fn linkgoods_list(&self, project_id: &str) -> String {
+        join(&self.show, &format!("/api/ticket/linkgoods/list?project_id={}&page_type=0", project_id))
+    }
+
+    pub fn linkgoods_detail(&self, link_id: &str) -> String {
+        join(&self.show, &format!("/api/ticket/linkgoods/detail?link_id={}", link_id))
+    }
+
+    pub fn buyer_list(&self, project_id: &str) -> String {
+        join(&self.show, &format!("/api/ticket/buyer/list?is_default&projectId={}", project_id))
+    }
+
+    pub fn address_list(&self) -> String {
+        join(&self.show, "/api/ticket/addr/list")
+    }
+
+    pub fn order_prepare(&self, project_id: &str) -> String {
+        join(&self.show, &format!("/api/ticket/order/prepare?project_id={}", project_id))
+    }
+
+    /// `createV2` without query string, as sent in the `orderCreateUrl` field.
+    pub fn order_create_base(&self) -> String {
+        join(&self.show, "/api/ticket/order/createV2")
+    }
+
+    pub fn order_create(&self, project_id: &str) -> String {
+        format!("{}?project_id={}", self.order_create_base(), project_id)
+    }
+
+    pub fn pay_param(&self, order_id: &str) -> String {
+        join(&self.show, &format!("/api/ticket/order/getPayParam?order_id={}", order_id))
+    }
+
+    pub fn user_nav(&self) -> String {
+        join(&self.api, "/x/web-interface/nav")
+    }
+
+    /// Bilibili server time, the default time source.
+    pub fn click_now(&self) -> String {
+        join(&self.api, "/x/report/click/now")
+    }
+
+    pub fn qrcode_generate(&self) -> String {
+        join(&self.passport, "/x/passport-login/web/qrcode/generate")
+    }
+
+    pub fn qrcode_poll(&self) -> String {
+        join(&self.passport, "/x/passport-login/web/qrcode/poll")
+    }
+}
+
+/// Backend configuration, read from `config.json` in the app directory.
+/// Missing fields fall back to their defaults.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Config {
+    pub endpoints: Endpoints,
+}
+
+impl Config {
+    pub fn load(base_dir: &Path) -> Result<Config> {
+        let path = base_dir.join("config.json");
+        if path.exists() {
+            let content = fs::read_to_string(&path)
+                .storage_context(|| format!("Failed to read config file {:?}", path))?;
+            let config = serde_json::from_str(&content)
+                .map_err(|e| Error::InvalidInput(format!("Invalid config file {:?}: {}", path, e)))?;
+            Ok(config)
+        } else {
+            Ok(Config::default())
+        }
+    }
+}
+
+/// Version written to `settings.json`. Bump it and extend
+/// [`Settings::migrate`] when a field changes meaning.
+pub const SETTINGS_VERSION: u32 = 2;
+
+/// Push notification tokens, sent when a task finishes.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Notifications {
+    pub pushplus: String,
+    pub serverchan: String,
+    pub bark: String,
+    pub ntfy: String,
+}
+
+/// Defaults for new buy tasks.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct BuyDefaults {
+    /// Delay between create attempts in ms
+    pub interval: u64,
+    /// 0: retry until stopped, 1: stop after `total_attempts` rounds
+    pub mode: u32,
+    pub total_attempts: u32,
+    /// Stop tasks for the same account and buyers once one of them orders
+    pub exclusive: bool,
+    /// Delay between project polls in watch mode in ms. Each poll also
+    /// fetches linked goods, so keep it well above `interval`.
+    pub watch_interval: u64,
+    /// Give up after this many failed prepares in a row, 0 for no limit
+    pub max_prepare_failures: u32,
+    /// Give up after this many prepare and create requests, 0 for no limit
+    pub max_requests: u32,
+    /// Errnos after which retrying is pointless, e.g. the sale has ended
+    pub stop_errnos: Vec<i64>,
+}
+
+impl Default for BuyDefaults {
+    fn default() -> Self {
+        Self {
+            interval: 1000,
+            mode: 0,
+            total_attempts: 10,
+            exclusive: false,
+            watch_interval: 3000,
+            max_prepare_failures: 0,
+            max_requests: 0,
+            stop_errnos: vec![100016, 100039],
+        }
+    }
+}
+
+/// Log files under `logs/` in the app directory, see [`crate::logfile`].
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct LogSettings {
+    pub level: LogLevel,
+    /// Start a new file once the current one reaches this size
+    pub max_file_mb: u64,
+    /// Files kept, oldest deleted first
+    pub max_files: usize,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            level: LogLevel::Info,
+            max_file_mb: 10,
+            max_files: 14,
+        }
+    }
+}
+
+/// User settings, read from `settings.json` in the app directory.
+/// Unlike [`Config`] they are edited from the UI.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    /// 0 for files written before settings were versioned
+    #[serde(default)]
+    pub version: u32,
+    pub proxy: Option<String>,
+    /// HTTP time API or NTP host; `None` uses [`Endpoints::click_now`]
+    pub ntp_server: Option<String>,
+    /// Time servers tried in order before `ntp_server`, see
+    /// [`timesync::sources`](crate::timesync::sources)
+    pub time_sources: Vec<TimeSource>,
+    /// Time sync interval in ms while tasks wait for their start time, 0 =
+    /// only sync on demand, see [`Clock::refresh`](crate::clock::Clock::refresh)
+    pub sync_interval: u64,
+    pub notifications: Notifications,
+    /// Recently entered project ids, newest first
+    pub recent_inputs: Vec<String>,
+    pub buy: BuyDefaults,
+    pub logging: LogSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            proxy: None,
+            ntp_server: None,
+            time_sources: Vec::new(),
+            sync_interval: 10_000,
+            notifications: Notifications::default(),
+            recent_inputs: Vec::new(),
+            buy: BuyDefaults::default(),
+            logging: LogSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load(base_dir: &Path) -> Result<Settings> {
+        let path = base_dir.join("settings.json");
+        if !path.exists() {
+            return Ok(Settings::default());
+        }
+        let content = fs::read_to_string(&path)
+            .storage_context(|| format!("Failed to read settings file {:?}", path))?;
+        let settings: Settings = serde_json::from_str(&content)
+            .map_err(|e| Error::InvalidInput(format!("Invalid settings file {:?}: {}", path, e)))?;
+        Ok(settings.migrate())
+    }
+
+    pub fn save(&self, base_dir: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        atomic_write(&base_dir.join("settings.json"), &content)
+    }
+
+    /// Bring settings written by an older version up to date.
+    fn migrate(mut self) -> Settings {
+        if self.version > SETTINGS_VERSION {
+            log::warn!("settings.json has version {}, newer than {}; unknown fields are dropped", self.version, SETTINGS_VERSION);
+        }
+        // Version 0 only lacked the version field. Before version 2 a
+        // `sync_interval` of 0 was just the default, written out as is.
+        if self.version < 2 && self.sync_interval == 0 {
+            self.sync_interval = Settings::default().sync_interval;
+        }
+        self.version = SETTINGS_VERSION;
+        self
+    }
+
+    /// Apply a partial update: objects are merged key by key, any other
+    /// value replaces the current one.
+    pub fn merged(&self, patch: serde_json::Value) -> Result<Settings> {
+        fn merge(target: &mut serde_json::Value, patch: serde_json::Value) {
+            match (target, patch) {
+                (serde_json::Value::Object(target), serde_json::Value::Object(patch)) => {
+                    for (key, value) in patch {
+                        merge(target.entry(key).or_insert(serde_json::Value::Null), value);
+                    }
+                }
+                (target, patch) => *target = patch,
+            }
+        }
+
+        let mut value = serde_json::to_value(self)?;
+        merge(&mut value, patch);
+        let mut settings: Settings = serde_json::from_value(value)
+            .map_err(|e| Error::InvalidInput(format!("Invalid settings: {}", e)))?;
+        settings.version = SETTINGS_VERSION;
+        Ok(settings)
+    }
+}
@@ -0,0 +1,151 @@
+//! Error type shared by the core and every Tauri command.
+//!
+//! Errors serialize to `{ kind, code?, message }` so the frontend can branch
+//! on a stable `kind` and the Bilibili code/errno instead of parsing text.
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::fmt::Display;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Stable, machine-readable error category sent to the frontend as `kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Connection failed, timed out or was reset
+    Network,
+    /// Not logged in or cookies expired
+    Auth,
+    /// Project, account or other entity does not exist
+    NotFound,
+    /// Bilibili answered with a non-zero code/errno
+    Api,
+    /// Bilibili answered with something we could not parse
+    InvalidResponse,
+    /// Bad arguments or local configuration
+    InvalidInput,
+    /// Reading or writing local files failed
+    Storage,
+    Internal,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    Network(String),
+    #[error("{message}")]
+    Auth { code: Option<i64>, message: String },
+    #[error("{0}")]
+    NotFound(String),
+    #[error("API error {code}: {message}")]
+    Api { code: i64, message: String },
+    #[error("{0}")]
+    InvalidResponse(String),
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("{0}")]
+    Storage(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+/// Bilibili codes meaning the request lacked a valid login.
+fn is_auth_code(code: i64) -> bool {
+    matches!(code, -101 | -111)
+}
+
+impl Error {
+    /// Error for a non-zero Bilibili code/errno.
+    pub fn api(code: i64, message: impl Into<String>) -> Self {
+        let message = message.into();
+        if is_auth_code(code) {
+            Error::Auth { code: Some(code), message }
+        } else {
+            Error::Api { code, message }
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Network(_) => ErrorKind::Network,
+            Error::Auth { .. } => ErrorKind::Auth,
+            Error::NotFound(_) => ErrorKind::NotFound,
+            Error::Api { .. } => ErrorKind::Api,
+            Error::InvalidResponse(_) => ErrorKind::InvalidResponse,
+            Error::InvalidInput(_) => ErrorKind::InvalidInput,
+            Error::Storage(_) => ErrorKind::Storage,
+            Error::Internal(_) => ErrorKind::Internal,
+        }
+    }
+
+    /// Bilibili code/errno, when the error came from one.
+    pub fn code(&self) -> Option<i64> {
+        match self {
+            Error::Auth { code, .. } => *code,
+            Error::Api { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+/// Serialized as `{"kind": "...", "code": 100001, "message": "..."}`;
+/// `code` is omitted when there is none.
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let code = self.code();
+        let mut s = serializer.serialize_struct("Error", if code.is_some() { 3 } else { 2 })?;
+        s.serialize_field("kind", &self.kind())?;
+        if let Some(code) = code {
+            s.serialize_field("code", &code)?;
+        }
+        s.serialize_field("message", &self.to_string())?;
+        s.end()
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            Error::InvalidResponse(e.to_string())
+        } else {
+            Error::Network(e.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::InvalidResponse(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Storage(e.to_string())
+    }
+}
+
+impl From<std::time::SystemTimeError> for Error {
+    fn from(e: std::time::SystemTimeError) -> Self {
+        Error::Internal(format!("System clock error: {}", e))
+    }
+}
+
+impl From<tokio::task::JoinError> for Error {
+    fn from(e: tokio::task::JoinError) -> Self {
+        Error::Internal(format!("Task join error: {}", e))
+    }
+}
+
+/// `with_context` for local file operations: wraps any error as
+/// [`Error::Storage`] prefixed by the given description.
+pub trait StorageContext<T> {
+    fn storage_context<F: FnOnce() -> String>(self, f: F) -> Result<T>;
+}
+
+impl<T, E: Display> StorageContext<T> for std::result::Result<T, E> {
+    fn storage_context<F: FnOnce() -> String>(self, f: F) -> Result<T> {
+        self.map_err(|e| Error::Storage(format!("{}: {}", f(), e)))
+    }
+}
@@ -0,0 +1,109 @@
+use crate::error::Result;
+use crate::phase::Phase;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Receiver for events emitted by a running buy task.
+///
+/// The Tauri app forwards these to the webview with `Window::emit`; other
+/// front ends can print them, collect them in tests, or drop them.
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: &str, payload: Value) -> Result<()>;
+}
+
+/// Sink that discards every event.
+pub struct NullSink;
+
+impl EventSink for NullSink {
+    fn emit(&self, _event: &str, _payload: Value) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Why a task ended, see [`TaskEvent::TaskFinished`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    OrderCreated,
+    AttemptsExhausted,
+    Stopped,
+    /// Another task for the same account and buyers ordered first
+    SiblingSucceeded,
+    /// `time_end` passed
+    DeadlineReached,
+    /// `max_prepare_failures` prepares failed in a row
+    PrepareFailures,
+    /// `max_requests` prepare and create requests were sent
+    RequestLimit,
+    /// An errno of `stop_errnos`, e.g. the sale has ended
+    TerminalErrno,
+}
+
+/// Structured events of a buy task, next to the human readable `log` lines.
+///
+/// Each event is emitted under the name returned by [`TaskEvent::name`]. Its
+/// payload holds the variant's fields plus `task_id` and `event` (the name
+/// again), so a payload parses back with `serde_json::from_value`. The JSON
+/// schema is in `docs/events.schema.json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TaskEvent {
+    /// Waiting for `time_start`; `offset_ms` is server minus local time
+    TaskScheduled { time_start: String, offset_ms: i64 },
+    /// Sent about once a second while waiting
+    Countdown { remaining_ms: i64, offset_ms: i64 },
+    TaskRescheduled { time_start: String },
+    TaskPhase {
+        phase: Phase,
+        /// Creates made in the current round
+        attempt: u32,
+    },
+    TaskPaused { paused: bool, phase: Phase },
+    /// The watched SKU can be bought again; the task starts preparing
+    Restocked { screen_id: String, sku_id: String, sale_flag: Option<i64> },
+    /// `errno` is absent when the request itself failed
+    PrepareResult { errno: Option<i64>, latency_ms: u64, message: String },
+    AttemptResult {
+        /// Creates made by the task so far, this one included
+        attempt: u32,
+        /// Position in the current round, from 1
+        round_attempt: u32,
+        round_size: u32,
+        /// Absent when the request itself failed
+        errno: Option<i64>,
+        latency_ms: u64,
+        message: String,
+    },
+    /// Moved on to the next fallback SKU after `errno`
+    SkuSwitched { errno: i64, screen_id: String, sku_id: String, sku_name: Option<String>, pay_money: u32 },
+    /// `order_id` is absent when the order already existed (100048/100079)
+    OrderCreated { order_id: Option<String>, price: Option<u32> },
+    PaymentReady { order_id: String, url: String, price: Option<u32> },
+    TaskFinished { reason: FinishReason, success: bool, message: String },
+}
+
+impl TaskEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TaskEvent::TaskScheduled { .. } => "task_scheduled",
+            TaskEvent::Countdown { .. } => "countdown",
+            TaskEvent::TaskRescheduled { .. } => "task_rescheduled",
+            TaskEvent::TaskPhase { .. } => "task_phase",
+            TaskEvent::TaskPaused { .. } => "task_paused",
+            TaskEvent::Restocked { .. } => "restocked",
+            TaskEvent::PrepareResult { .. } => "prepare_result",
+            TaskEvent::AttemptResult { .. } => "attempt_result",
+            TaskEvent::SkuSwitched { .. } => "sku_switched",
+            TaskEvent::OrderCreated { .. } => "order_created",
+            TaskEvent::PaymentReady { .. } => "payment_ready",
+            TaskEvent::TaskFinished { .. } => "task_finished",
+        }
+    }
+
+    /// Emit the event for `task_id` to `sink`.
+    pub fn emit(&self, sink: &dyn EventSink, task_id: &str) -> Result<()> {
+        let mut payload = serde_json::to_value(self)?;
+        payload["task_id"] = Value::String(task_id.to_string());
+        sink.emit(self.name(), payload)
+    }
+}
@@ -0,0 +1,109 @@
+use reqwest::{Client, Url};
+use reqwest::cookie::Jar;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::error::{Error, Result};
+use crate::config::Endpoints;
+
+pub const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36 Edg/126.0.0.0";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Set-Cookie attributes that must not be sent back as cookies.
+const COOKIE_ATTRIBUTES: &[&str] = &["path", "domain", "expires", "max-age", "samesite", "secure", "httponly", "priority"];
+
+/// Split stored cookie strings (either `name=value` or full Set-Cookie
+/// lines) into plain `name=value` pairs.
+pub fn cookie_pairs(cookies: &[String]) -> Vec<String> {
+    cookies.iter()
+        .flat_map(|c| c.split(';'))
+        .map(|part| part.trim())
+        .filter(|part| match part.split_once('=') {
+            Some((name, _)) => !name.is_empty() && !COOKIE_ATTRIBUTES.contains(&name.trim().to_ascii_lowercase().as_str()),
+            None => false,
+        })
+        .map(|part| part.to_string())
+        .collect()
+}
+
+/// Account uid (`DedeUserID`) of stored cookies.
+pub fn cookie_uid(cookies: &[String]) -> Option<String> {
+    cookie_pairs(cookies).iter()
+        .find_map(|p| p.strip_prefix("DedeUserID="))
+        .map(|uid| uid.to_string())
+}
+
+/// Pool key for a cookie set: the `DedeUserID` (account uid) when present,
+/// otherwise a digest of the cookies.
+pub(crate) fn account_key(pairs: &[String]) -> String {
+    cookie_uid(pairs).unwrap_or_else(|| format!("{:x}", md5::compute(pairs.join("; "))))
+}
+
+fn builder() -> reqwest::ClientBuilder {
+    Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .tcp_keepalive(Duration::from_secs(30))
+}
+
+struct PooledClient {
+    /// Cookies the jar was filled with; a change (re-login) rebuilds the client
+    pairs: Vec<String>,
+    client: Client,
+}
+
+/// Long-lived HTTP clients, one per account plus one without cookies, so
+/// tasks and lookups for the same account reuse warm connections.
+pub struct ClientPool {
+    endpoints: Endpoints,
+    anonymous: Client,
+    accounts: Mutex<HashMap<String, PooledClient>>,
+}
+
+impl ClientPool {
+    pub fn new(endpoints: Endpoints) -> Result<Self> {
+        Ok(Self {
+            endpoints,
+            anonymous: builder().build()?,
+            accounts: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Client without a cookie store, for public endpoints and QR login.
+    pub fn anonymous(&self) -> Client {
+        self.anonymous.clone()
+    }
+
+    /// Client whose cookie jar holds `cookies` for every configured host.
+    pub fn for_cookies(&self, cookies: &[String]) -> Result<Client> {
+        let pairs = cookie_pairs(cookies);
+        let key = account_key(&pairs);
+
+        let mut accounts = self.accounts.lock().unwrap();
+        if let Some(pooled) = accounts.get(&key) {
+            if pooled.pairs == pairs {
+                return Ok(pooled.client.clone());
+            }
+        }
+
+        let jar = Arc::new(Jar::default());
+        for host in [&self.endpoints.show, &self.endpoints.api, &self.endpoints.passport] {
+            let url = host.parse::<Url>()
+                .map_err(|e| Error::InvalidInput(format!("Invalid endpoint {:?}: {}", host, e)))?;
+            for pair in &pairs {
+                jar.add_cookie_str(pair, &url);
+            }
+        }
+        let client = builder().cookie_provider(jar).build()?;
+        accounts.insert(key, PooledClient { pairs, client: client.clone() });
+        Ok(client)
+    }
+
+    /// Drop the pooled client of an account, e.g. after it is removed.
+    pub fn evict(&self, uid: &str) {
+        self.accounts.lock().unwrap().remove(uid);
+    }
+}
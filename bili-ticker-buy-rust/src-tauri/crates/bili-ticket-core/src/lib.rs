@@ -0,0 +1,22 @@
+//! Core of biliTickerBuy: project lookup, login, account storage and the
+//! purchase flow, with no dependency on Tauri.
+//!
+//! Front ends (the Tauri app, the CLI) receive task output through an
+//! [`event::EventSink`] implementation of their own.
+
+pub mod api;
+pub mod auth;
+pub mod buy;
+pub mod clock;
+pub mod config;
+pub mod error;
+pub mod event;
+pub mod http;
+pub mod logfile;
+pub mod logs;
+pub mod models;
+pub mod phase;
+pub mod storage;
+pub mod task;
+pub mod timesync;
+pub mod util;
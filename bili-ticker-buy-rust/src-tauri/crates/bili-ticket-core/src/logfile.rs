@@ -0,0 +1,161 @@
+//! Log files for post-mortems: everything logged through the `log` crate is
+//! appended to `bili-ticket-YYYY-MM-DD.log` in the log directory. A file
+//! that grows past the size limit continues in `bili-ticket-YYYY-MM-DD.1.log`
+//! and so on, and only the newest `max_files` files are kept.
+
+use chrono::{Local, NaiveDate};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use crate::config::LogSettings;
+use crate::error::{Result, StorageContext};
+
+const PREFIX: &str = "bili-ticket-";
+
+/// `(date, index)` of a log file written by [`FileLogger`].
+fn parse_name(name: &str) -> Option<(NaiveDate, u32)> {
+    let stem = name.strip_prefix(PREFIX)?.strip_suffix(".log")?;
+    let (date, index) = match stem.split_once('.') {
+        Some((date, index)) => (date, index.parse().ok()?),
+        None => (stem, 0),
+    };
+    Some((NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?, index))
+}
+
+fn file_name(date: NaiveDate, index: u32) -> String {
+    match index {
+        0 => format!("{}{}.log", PREFIX, date.format("%Y-%m-%d")),
+        n => format!("{}{}.{}.log", PREFIX, date.format("%Y-%m-%d"), n),
+    }
+}
+
+struct Current {
+    date: NaiveDate,
+    index: u32,
+    file: File,
+    size: u64,
+}
+
+pub struct FileLogger {
+    dir: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    current: Mutex<Option<Current>>,
+}
+
+impl FileLogger {
+    pub fn new(dir: &Path, settings: &LogSettings) -> Result<Self> {
+        fs::create_dir_all(dir).storage_context(|| format!("Failed to create log directory {:?}", dir))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            max_size: settings.max_file_mb.max(1) * 1024 * 1024,
+            max_files: settings.max_files.max(1),
+            current: Mutex::new(None),
+        })
+    }
+
+    /// Log files in the directory, oldest first.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<_> = fs::read_dir(&self.dir).into_iter().flatten().flatten()
+            .filter_map(|e| {
+                let key = parse_name(&e.file_name().to_string_lossy())?;
+                Some((key, e.path()))
+            })
+            .collect();
+        files.sort();
+        files.into_iter().map(|(_, path)| path).collect()
+    }
+
+    fn open(&self, date: NaiveDate, index: u32) -> std::io::Result<Current> {
+        let path = self.dir.join(file_name(date, index));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Current { date, index, file, size })
+    }
+
+    /// File to write `len` more bytes to, moving on to a new one when the
+    /// day changed or the current file is full.
+    fn current<'a>(&self, current: &'a mut Option<Current>, len: u64) -> std::io::Result<&'a mut Current> {
+        let today = Local::now().date_naive();
+        let next = match current.as_ref() {
+            None => {
+                // Continue the newest file of today after a restart
+                let index = self.files().iter()
+                    .filter_map(|p| parse_name(&p.file_name()?.to_string_lossy()))
+                    .filter(|(date, _)| *date == today)
+                    .map(|(_, index)| index)
+                    .max()
+                    .unwrap_or(0);
+                Some(index)
+            }
+            Some(c) if c.date != today => Some(0),
+            Some(c) if c.size > 0 && c.size + len > self.max_size => Some(c.index + 1),
+            Some(_) => None,
+        };
+        if let Some(index) = next {
+            *current = Some(self.open(today, index)?);
+            self.prune();
+        }
+        Ok(current.as_mut().unwrap())
+    }
+
+    fn prune(&self) {
+        let files = self.files();
+        for path in files.iter().take(files.len().saturating_sub(self.max_files)) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    fn write(&self, line: &str) -> std::io::Result<()> {
+        let mut current = self.current.lock().unwrap();
+        let file = self.current(&mut current, line.len() as u64)?;
+        file.file.write_all(line.as_bytes())?;
+        file.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Dependencies (HTTP client, webview) only when they warn
+        let ours = metadata.target().starts_with("bili_ticke");
+        metadata.level() <= log::max_level() && (ours || metadata.level() <= log::Level::Warn)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{} {:<5} {} {}\n",
+            Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        // Nowhere left to report a failed write
+        let _ = self.write(&line);
+    }
+
+    fn flush(&self) {
+        if let Some(current) = self.current.lock().unwrap().as_mut() {
+            let _ = current.file.flush();
+        }
+    }
+}
+
+/// Install a [`FileLogger`] writing to `dir` as the global logger.
+pub fn init(dir: &Path, settings: &LogSettings) -> Result<()> {
+    let logger = FileLogger::new(dir, settings)?;
+    log::set_boxed_logger(Box::new(logger))
+        .map_err(|e| crate::error::Error::Internal(format!("Logger already set: {}", e)))?;
+    set_level(settings);
+    Ok(())
+}
+
+/// Apply a changed log level to the installed logger.
+pub fn set_level(settings: &LogSettings) {
+    log::set_max_level(LevelFilter::from(settings.level));
+}
@@ -0,0 +1,141 @@
+//! Bounded log of each task, kept in the backend so the lines survive a
+//! window reload and can be queried or exported.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use crate::error::Result;
+use crate::phase::Phase;
+
+/// Lines kept per task; older lines are dropped first.
+pub const LOG_CAPACITY: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl From<LogLevel> for log::Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Debug => log::Level::Debug,
+            LogLevel::Info => log::Level::Info,
+            LogLevel::Warn => log::Level::Warn,
+            LogLevel::Error => log::Level::Error,
+        }
+    }
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        log::Level::from(level).to_level_filter()
+    }
+}
+
+impl LogLevel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Info => "INFO",
+            LogLevel::Warn => "WARN",
+            LogLevel::Error => "ERROR",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// Increases by one per line of the task, starting at 1
+    pub seq: u64,
+    pub time: String,
+    pub level: LogLevel,
+    /// Phase of the task when the line was logged
+    pub phase: Phase,
+    pub message: String,
+}
+
+/// Which lines to return; every field is optional.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LogQuery {
+    /// Only lines after this `seq`
+    pub since: Option<u64>,
+    /// At most this many lines, oldest first
+    pub limit: Option<usize>,
+    /// Only lines of this level or above
+    pub level: Option<LogLevel>,
+    pub phase: Option<Phase>,
+}
+
+impl LogQuery {
+    fn matches(&self, entry: &LogEntry) -> bool {
+        self.since.is_none_or(|since| entry.seq > since)
+            && self.level.is_none_or(|level| entry.level >= level)
+            && self.phase.is_none_or(|phase| entry.phase == phase)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug)]
+pub struct LogBuffer {
+    entries: VecDeque<LogEntry>,
+    capacity: usize,
+    next_seq: u64,
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::new(LOG_CAPACITY)
+    }
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self { entries: VecDeque::new(), capacity: capacity.max(1), next_seq: 1 }
+    }
+
+    pub fn push(&mut self, level: LogLevel, phase: Phase, message: &str) -> LogEntry {
+        let entry = LogEntry {
+            seq: self.next_seq,
+            time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            level,
+            phase,
+            message: message.to_string(),
+        };
+        self.next_seq += 1;
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry.clone());
+        entry
+    }
+
+    pub fn query(&self, query: &LogQuery) -> Vec<LogEntry> {
+        self.entries.iter()
+            .filter(|e| query.matches(e))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Render lines as `time LEVEL [phase] message` text or a JSON array.
+pub fn export(entries: &[LogEntry], format: ExportFormat) -> Result<String> {
+    Ok(match format {
+        ExportFormat::Json => serde_json::to_string_pretty(entries)?,
+        ExportFormat::Text => entries.iter()
+            .map(|e| {
+                let phase = serde_json::to_value(e.phase)?;
+                Ok(format!("{} {:<5} [{}] {}\n", e.time, e.level.as_str(), phase.as_str().unwrap_or_default(), e.message))
+            })
+            .collect::<Result<String>>()?,
+    })
+}
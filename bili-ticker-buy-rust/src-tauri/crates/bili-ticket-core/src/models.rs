@@ -0,0 +1,316 @@
+//! Typed Bilibili API responses.
+//!
+//! Only the fields the backend relies on are typed; everything else is kept
+//! in `extra` so responses still reach the frontend unchanged. Numeric
+//! fields accept numbers or numeric strings, since Bilibili mixes both.
+
+use serde::de::{DeserializeOwned, Deserializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use chrono::{DateTime, Local, TimeZone};
+use crate::error::{Error, Result};
+
+fn flexible_i64<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<i64, D::Error> {
+    match Value::deserialize(d)? {
+        Value::Number(n) => n.as_i64()
+            .or_else(|| n.as_f64().map(|f| f as i64))
+            .ok_or_else(|| serde::de::Error::custom(format!("number out of range: {}", n))),
+        Value::String(s) => s.trim().parse().map_err(|_| serde::de::Error::custom(format!("expected a number, got {:?}", s))),
+        Value::Null => Ok(0),
+        other => Err(serde::de::Error::custom(format!("expected a number, got {}", other))),
+    }
+}
+
+fn flexible_opt_i64<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Option<i64>, D::Error> {
+    match Value::deserialize(d)? {
+        Value::Null => Ok(None),
+        v => flexible_i64(v).map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+fn flexible_f64<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<f64, D::Error> {
+    match Value::deserialize(d)? {
+        Value::Number(n) => Ok(n.as_f64().unwrap_or(0.0)),
+        Value::String(s) => s.trim().parse().map_err(|_| serde::de::Error::custom(format!("expected a number, got {:?}", s))),
+        Value::Null => Ok(0.0),
+        other => Err(serde::de::Error::custom(format!("expected a number, got {}", other))),
+    }
+}
+
+fn flexible_bool<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<bool, D::Error> {
+    match Value::deserialize(d)? {
+        Value::Bool(b) => Ok(b),
+        Value::Number(n) => Ok(n.as_i64().unwrap_or(0) != 0),
+        Value::String(s) => Ok(s == "1" || s.eq_ignore_ascii_case("true")),
+        _ => Ok(false),
+    }
+}
+
+/// Response envelope shared by the ticket (`errno`/`msg`) and main site
+/// (`code`/`message`) APIs.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiResponse<T> {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub errno: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub msg: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default = "Option::default")]
+    pub data: Option<T>,
+}
+
+impl<T> ApiResponse<T> {
+    /// `errno` when present, else `code`, else -1.
+    pub fn status(&self) -> i64 {
+        self.errno.or(self.code).unwrap_or(-1)
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.status() == 0
+    }
+
+    pub fn message(&self) -> &str {
+        self.msg.as_deref().filter(|m| !m.is_empty())
+            .or(self.message.as_deref())
+            .unwrap_or("")
+    }
+
+    /// The payload of a successful response.
+    pub fn into_data(self) -> Result<T> {
+        if !self.is_ok() {
+            return Err(Error::api(self.status(), self.message()));
+        }
+        self.data.ok_or_else(|| Error::InvalidResponse("API response has no data".to_string()))
+    }
+}
+
+impl<T: DeserializeOwned> ApiResponse<T> {
+    /// Parse a raw response. `what` names the endpoint in error messages.
+    ///
+    /// `data` of a failed call is often `{}`, `[]` or null, so it is only
+    /// required to match `T` when the call succeeded.
+    pub fn parse(raw: Value, what: &str) -> Result<Self> {
+        let envelope: ApiResponse<Value> = serde_json::from_value(raw)
+            .map_err(|e| Error::InvalidResponse(format!("Malformed {} response: {}", what, e)))?;
+        let ok = envelope.is_ok();
+        let data = match envelope.data {
+            None | Some(Value::Null) => None,
+            Some(d) if ok => Some(serde_json::from_value(d).map_err(|e| Error::InvalidResponse(format!("Unexpected {} response: {}", what, e)))?),
+            Some(d) => serde_json::from_value(d).ok(),
+        };
+        Ok(ApiResponse {
+            errno: envelope.errno,
+            code: envelope.code,
+            msg: envelope.msg,
+            message: envelope.message,
+            data,
+        })
+    }
+}
+
+/// `project/getV2` data.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Project {
+    #[serde(deserialize_with = "flexible_i64")]
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, deserialize_with = "flexible_bool")]
+    pub has_eticket: bool,
+    #[serde(default)]
+    pub screen_list: Vec<Screen>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A screen (场次) of a project, or a spec of linked goods merged into
+/// `screen_list`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Screen {
+    #[serde(deserialize_with = "flexible_i64")]
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+    /// Negative means no delivery
+    #[serde(default, deserialize_with = "flexible_i64")]
+    pub express_fee: i64,
+    #[serde(default)]
+    pub ticket_list: Vec<Sku>,
+    /// Set on linked goods: the goods' own project (`item_id`)
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "flexible_opt_i64")]
+    pub project_id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_id: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A ticket type (票种) of a screen.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Sku {
+    #[serde(deserialize_with = "flexible_i64")]
+    pub id: i64,
+    #[serde(default)]
+    pub desc: String,
+    /// In fen (分)
+    #[serde(deserialize_with = "flexible_i64")]
+    pub price: i64,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Project {
+    pub fn sku(&self, screen_id: &str, sku_id: &str) -> Option<&Sku> {
+        self.screen_list.iter()
+            .filter(|s| s.id.to_string() == screen_id)
+            .flat_map(|s| s.ticket_list.iter())
+            .find(|sku| sku.id.to_string() == sku_id)
+    }
+
+    /// Published sale start of a SKU, falling back to its screen's.
+    pub fn sale_start(&self, screen_id: &str, sku_id: &str) -> Option<DateTime<Local>> {
+        let screen = self.screen_list.iter().find(|s| s.id.to_string() == screen_id)?;
+        let sku = screen.ticket_list.iter().find(|sku| sku.id.to_string() == sku_id)?;
+        sale_start(&sku.extra).or_else(|| sale_start(&screen.extra))
+    }
+}
+
+/// `saleStart` in unix seconds, or `sale_start` as seconds or local
+/// `%Y-%m-%d %H:%M:%S`. 0 means not published.
+fn sale_start(extra: &Map<String, Value>) -> Option<DateTime<Local>> {
+    let value = extra.get("saleStart").filter(|v| v.as_i64().is_some()).or_else(|| extra.get("sale_start"))?;
+    match value {
+        Value::Number(n) => n.as_i64().filter(|&secs| secs > 0).and_then(|secs| Local.timestamp_opt(secs, 0).single()),
+        Value::String(s) => crate::buy::parse_start_time(s.trim()),
+        _ => None,
+    }
+}
+
+impl Sku {
+    /// `sale_flag.number`, or `sale_flag_number` in some responses: 2 on
+    /// sale, 4 sold out, 6 low on stock, 8 temporarily sold out, ...
+    pub fn sale_flag(&self) -> Option<i64> {
+        self.extra.get("sale_flag").and_then(|f| f["number"].as_i64())
+            .or_else(|| self.extra.get("sale_flag_number").and_then(Value::as_i64))
+    }
+
+    /// Can be picked on the web page. Absent means it can.
+    pub fn clickable(&self) -> bool {
+        self.extra.get("clickable").and_then(Value::as_bool).unwrap_or(true)
+    }
+
+    /// Clickable and on sale or low on stock.
+    pub fn purchasable(&self) -> bool {
+        self.clickable() && matches!(self.sale_flag(), None | Some(2) | Some(6))
+    }
+}
+
+/// `linkgoods/list` data.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LinkGoodsList {
+    #[serde(default)]
+    pub list: Vec<LinkGoods>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LinkGoods {
+    #[serde(deserialize_with = "flexible_i64")]
+    pub id: i64,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// `linkgoods/detail` data.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LinkGoodsDetail {
+    #[serde(deserialize_with = "flexible_i64")]
+    pub item_id: i64,
+    #[serde(default)]
+    pub specs_list: Vec<Screen>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// `buyer/list` data.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BuyerList {
+    #[serde(default)]
+    pub list: Vec<Buyer>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Buyer {
+    #[serde(deserialize_with = "flexible_i64")]
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+    /// Masked ID number
+    #[serde(default)]
+    pub personal_id: String,
+    /// Masked phone number
+    #[serde(default)]
+    pub tel: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// `addr/list` data.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AddressList {
+    #[serde(default)]
+    pub addr_list: Vec<Address>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Address {
+    #[serde(deserialize_with = "flexible_i64")]
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub phone: String,
+    #[serde(default)]
+    pub prov: String,
+    #[serde(default)]
+    pub city: String,
+    #[serde(default)]
+    pub area: String,
+    #[serde(default)]
+    pub addr: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// `x/web-interface/nav` data of a logged-in user.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UserNav {
+    #[serde(deserialize_with = "flexible_i64")]
+    pub mid: i64,
+    #[serde(default)]
+    pub uname: String,
+    #[serde(default)]
+    pub face: String,
+    #[serde(default)]
+    pub level_info: LevelInfo,
+    #[serde(rename = "vipStatus", default, deserialize_with = "flexible_i64")]
+    pub vip_status: i64,
+    #[serde(default, deserialize_with = "flexible_f64")]
+    pub money: f64,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LevelInfo {
+    #[serde(default, deserialize_with = "flexible_i64")]
+    pub current_level: i64,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
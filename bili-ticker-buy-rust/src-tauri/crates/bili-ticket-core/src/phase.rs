@@ -0,0 +1,184 @@
+//! Phases of a buy task and the transitions between them.
+//!
+//! ```text
+//! Scheduled        StartTimeReached      -> Preparing, or Watching in watch mode
+//! Watching         Restocked             -> Preparing
+//!                  NotOnSale             -> Watching
+//! Preparing        Prepared              -> Creating
+//!                  PrepareFailed         -> end of round
+//!                  SkuSwitched           -> Preparing
+//!                  SoldOut               -> Watching
+//! Creating         Created (order id)    -> FetchingPayment
+//!                  Created (no order id) -> Done
+//!                  CreateFailed          -> Creating, or end of round
+//!                  TokenExpired          -> end of round
+//!                  SkuSwitched           -> Preparing
+//!                  SoldOut               -> Watching
+//! FetchingPayment  PaymentReady/Failed   -> Done
+//! end of round                           -> Preparing, or Failed when no rounds are left
+//! ```
+//!
+//! `Stop` moves any running phase to `Stopped`, and `GiveUp` any phase but
+//! `FetchingPayment` to `Failed`. [`PhaseMachine`] only decides
+//! the next phase; the requests themselves are made by [`crate::buy`].
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Waiting for the start time
+    Scheduled,
+    /// Polling the project until the SKU can be bought (watch mode)
+    Watching,
+    /// Requesting an order token (`order/prepare`)
+    Preparing,
+    /// Submitting the order with the current token (`order/createV2`)
+    Creating,
+    /// Order created, fetching the payment QR code
+    FetchingPayment,
+    Done,
+    /// Ran out of attempts or gave up, see
+    /// [`FinishReason`](crate::event::FinishReason) for which
+    Failed,
+    /// Stopped by the user
+    Stopped,
+}
+
+impl Phase {
+    pub fn is_terminal(self) -> bool {
+        matches!(self, Phase::Done | Phase::Failed | Phase::Stopped)
+    }
+}
+
+/// What happened in the current phase.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    StartTimeReached,
+    /// The watched SKU can be bought
+    Restocked,
+    NotOnSale,
+    Prepared,
+    /// Non-zero errno or request error from `order/prepare`
+    PrepareFailed,
+    /// Order placed (errno 0) or already placed (100048/100079).
+    /// `has_order` is false when there is no order id to pay for.
+    Created { has_order: bool },
+    /// Congestion, price change, request error or any other retryable errno
+    CreateFailed,
+    /// 100051: the prepare token expired
+    TokenExpired,
+    /// Sold out, moved on to a fallback SKU that needs its own token
+    SkuSwitched,
+    /// Sold out with no fallback left; only sent in watch mode
+    SoldOut,
+    PaymentReady,
+    PaymentFailed,
+    Stop,
+    /// A limit of the task was reached or an errno ruled out buying
+    GiveUp,
+}
+
+/// Decides the next [`Phase`] and keeps the attempt counters.
+///
+/// Each prepare starts a round of up to `attempts_per_round` creates. A
+/// round ends when it is used up or the token expires; in finite mode only
+/// `total_attempts` rounds are allowed before the task fails.
+#[derive(Debug, Clone)]
+pub struct PhaseMachine {
+    phase: Phase,
+    finite: bool,
+    watch: bool,
+    attempts_per_round: u32,
+    rounds_left: u32,
+    attempt: u32,
+}
+
+impl PhaseMachine {
+    /// `total_attempts` is both the creates per round and, when `finite`,
+    /// the number of rounds. 0 means 60 creates per round.
+    pub fn new(scheduled: bool, finite: bool, total_attempts: u32) -> Self {
+        Self {
+            phase: if scheduled { Phase::Scheduled } else { Phase::Preparing },
+            finite,
+            watch: false,
+            attempts_per_round: if total_attempts > 0 { total_attempts } else { 60 },
+            rounds_left: total_attempts,
+            attempt: 0,
+        }
+    }
+
+    /// Poll for stock before preparing, and again whenever the SKU sells out.
+    pub fn with_watch(mut self, watch: bool) -> Self {
+        self.watch = watch;
+        if watch && self.phase == Phase::Preparing {
+            self.phase = Phase::Watching;
+        }
+        self
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Creates made in the current round.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn attempts_per_round(&self) -> u32 {
+        self.attempts_per_round
+    }
+
+    /// Apply `outcome` and return the new phase. Outcomes that do not
+    /// belong to the current phase leave it unchanged.
+    pub fn on(&mut self, outcome: Outcome) -> Phase {
+        use Outcome::*;
+        use Phase::*;
+
+        self.phase = match (self.phase, outcome) {
+            (phase, _) if phase.is_terminal() => phase,
+            (_, Stop) => Stopped,
+            (Scheduled | Watching | Preparing | Creating, GiveUp) => Failed,
+            (Scheduled, StartTimeReached) if self.watch => Watching,
+            (Scheduled, StartTimeReached) => Preparing,
+            (Watching, Restocked) => Preparing,
+            (Watching, NotOnSale) => Watching,
+            (Preparing | Creating, SoldOut) if self.watch => Watching,
+            (Preparing, Prepared) => {
+                self.attempt = 0;
+                Creating
+            }
+            // A round in finite mode, so a prepare that keeps failing ends too
+            (Preparing, PrepareFailed) => self.end_round(),
+            (Creating, Created { has_order: true }) => FetchingPayment,
+            (Creating, Created { has_order: false }) => Done,
+            (Creating, CreateFailed) => {
+                self.attempt += 1;
+                if self.attempt >= self.attempts_per_round {
+                    self.end_round()
+                } else {
+                    Creating
+                }
+            }
+            (Creating, TokenExpired) => {
+                self.attempt += 1;
+                self.end_round()
+            }
+            (Preparing | Creating, SkuSwitched) => Preparing,
+            (FetchingPayment, PaymentReady | PaymentFailed) => Done,
+            (phase, _) => phase,
+        };
+        self.phase
+    }
+
+    fn end_round(&mut self) -> Phase {
+        if self.finite {
+            self.rounds_left = self.rounds_left.saturating_sub(1);
+            if self.rounds_left == 0 {
+                return Phase::Failed;
+            }
+        }
+        Phase::Preparing
+    }
+}
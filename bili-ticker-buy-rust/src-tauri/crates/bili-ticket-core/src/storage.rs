@@ -1,20 +1,41 @@
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
-use anyhow::{Result, Context};
+use std::sync::Mutex;
+use crate::buy::{BuyOptions, TicketInfo};
+use crate::error::{Error, Result, StorageContext};
+
+/// Held for each write, so [`flush`] can wait for writes in progress.
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
 
 /// Atomically write content to a file by writing to a temp file first, then renaming.
 /// This prevents data corruption if the app crashes mid-write.
-fn atomic_write(path: &Path, content: &str) -> Result<()> {
+pub(crate) fn atomic_write(path: &Path, content: &str) -> Result<()> {
+    let _guard = WRITE_LOCK.lock().unwrap();
     let parent = path.parent().unwrap_or(Path::new("."));
     let temp_path = parent.join(format!("{}.tmp", path.file_name().unwrap_or_default().to_string_lossy()));
     fs::write(&temp_path, content)
-        .with_context(|| format!("Failed to write temp file: {:?}", temp_path))?;
+        .storage_context(|| format!("Failed to write temp file {:?}", temp_path))?;
     fs::rename(&temp_path, path)
-        .with_context(|| format!("Failed to rename temp file to: {:?}", path))?;
+        .storage_context(|| format!("Failed to rename temp file to {:?}", path))?;
     Ok(())
 }
 
+/// Wait for writes in progress, then remove `.tmp` files left in
+/// `base_dir` by writes that were cut off. Returns how many were removed.
+pub fn flush(base_dir: &Path) -> Result<usize> {
+    let _guard = WRITE_LOCK.lock().unwrap();
+    let mut removed = 0;
+    for entry in fs::read_dir(base_dir).storage_context(|| format!("Failed to read {:?}", base_dir))? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|e| e == "tmp") {
+            fs::remove_file(&path).storage_context(|| format!("Failed to remove {:?}", path))?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Account {
     pub uid: String,
@@ -29,6 +50,15 @@ pub struct Account {
     pub coins: f64,
 }
 
+/// A task definition saved in `tasks.json`, enough to start it again
+/// after a restart.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StoredTask {
+    pub task_id: String,
+    pub ticket_info: TicketInfo,
+    pub options: BuyOptions,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct HistoryItem {
     pub order_id: String,
@@ -53,7 +83,7 @@ pub fn get_accounts(base_dir: &Path) -> Result<Vec<Account>> {
     let path = base_dir.join("accounts.json");
     if path.exists() {
         let content = fs::read_to_string(&path)
-            .with_context(|| format!("Failed to read accounts file: {:?}", path))?;
+            .storage_context(|| format!("Failed to read accounts file {:?}", path))?;
         let accounts: Vec<Account> = serde_json::from_str(&content).unwrap_or_default();
         Ok(accounts)
     } else {
@@ -71,7 +101,7 @@ pub fn get_history(base_dir: &Path) -> Result<Vec<HistoryItem>> {
     let path = base_dir.join("history.json");
     if path.exists() {
         let content = fs::read_to_string(&path)
-            .with_context(|| format!("Failed to read history file: {:?}", path))?;
+            .storage_context(|| format!("Failed to read history file {:?}", path))?;
         let history: Vec<HistoryItem> = serde_json::from_str(&content).unwrap_or_default();
         Ok(history)
     } else {
@@ -96,7 +126,7 @@ pub fn get_project_history(base_dir: &Path) -> Result<Vec<ProjectConfig>> {
     let path = base_dir.join("project_history.json");
     if path.exists() {
         let content = fs::read_to_string(&path)
-            .with_context(|| format!("Failed to read project history file: {:?}", path))?;
+            .storage_context(|| format!("Failed to read project history file {:?}", path))?;
         let history: Vec<ProjectConfig> = serde_json::from_str(&content).unwrap_or_default();
         Ok(history)
     } else {
@@ -142,6 +172,57 @@ pub fn remove_project_history_item(base_dir: &Path, project_id: String, sku_id:
     atomic_write(&path, &json)
 }
 
+/// Convert an account's cookies into the `_default.1.value` JSON layout used
+/// by the Python version's cookie files.
+pub fn export_cookie_json(cookies: &[String]) -> serde_json::Value {
+    let mut cookie_items = Vec::new();
+    for c in cookies {
+        // c is like "name=value; ..."
+        let parts: Vec<&str> = c.split(';').collect();
+        if let Some(first) = parts.first() {
+            if let Some((name, value)) = first.split_once('=') {
+                cookie_items.push(serde_json::json!({
+                    "name": name.trim(),
+                    "value": value.trim()
+                }));
+            }
+        }
+    }
+
+    serde_json::json!({
+        "_default": {
+            "1": {
+                "key": "cookie",
+                "value": cookie_items
+            }
+        }
+    })
+}
+
+/// Parse a cookie file written by [`export_cookie_json`] (or the Python
+/// version) back into `name=value` strings.
+pub fn import_cookie_json(content: &str) -> Result<Vec<String>> {
+    let json: serde_json::Value = serde_json::from_str(content)
+        .map_err(|e| Error::InvalidInput(format!("Invalid cookie file: {}", e)))?;
+
+    let items = json["_default"]["1"]["value"].as_array()
+        .ok_or_else(|| Error::InvalidInput("Invalid format: missing _default.1.value".to_string()))?;
+
+    let mut cookies = Vec::new();
+    for item in items {
+        let name = item["name"].as_str().unwrap_or("");
+        let value = item["value"].as_str().unwrap_or("");
+        if !name.is_empty() {
+            cookies.push(format!("{}={}", name, value));
+        }
+    }
+
+    if cookies.is_empty() {
+        return Err(Error::InvalidInput("No cookies found in file".to_string()));
+    }
+    Ok(cookies)
+}
+
 pub fn save_cookies(base_dir: &Path, cookies: String) -> Result<()> {
     let path = base_dir.join("cookies.json");
     atomic_write(&path, &cookies)
@@ -155,3 +236,45 @@ pub fn load_cookies(base_dir: &Path) -> Result<String> {
         Ok("".to_string())
     }
 }
+
+/// Serializes read-modify-write of `tasks.json`; tasks finish concurrently.
+static TASKS_LOCK: Mutex<()> = Mutex::new(());
+
+fn read_tasks(path: &Path) -> Result<Vec<StoredTask>> {
+    if path.exists() {
+        let content = fs::read_to_string(path)
+            .storage_context(|| format!("Failed to read tasks file {:?}", path))?;
+        let tasks: Vec<StoredTask> = serde_json::from_str(&content).unwrap_or_default();
+        Ok(tasks)
+    } else {
+        Ok(vec![])
+    }
+}
+
+pub fn get_tasks(base_dir: &Path) -> Result<Vec<StoredTask>> {
+    let _guard = TASKS_LOCK.lock().unwrap();
+    read_tasks(&base_dir.join("tasks.json"))
+}
+
+pub fn save_task(base_dir: &Path, task: StoredTask) -> Result<()> {
+    let _guard = TASKS_LOCK.lock().unwrap();
+    let path = base_dir.join("tasks.json");
+    let mut tasks = read_tasks(&path)?;
+    tasks.retain(|t| t.task_id != task.task_id);
+    tasks.push(task);
+    let json = serde_json::to_string_pretty(&tasks)?;
+    atomic_write(&path, &json)
+}
+
+pub fn remove_task(base_dir: &Path, task_id: &str) -> Result<()> {
+    let _guard = TASKS_LOCK.lock().unwrap();
+    let path = base_dir.join("tasks.json");
+    let mut tasks = read_tasks(&path)?;
+    let before = tasks.len();
+    tasks.retain(|t| t.task_id != task_id);
+    if tasks.len() == before {
+        return Ok(());
+    }
+    let json = serde_json::to_string_pretty(&tasks)?;
+    atomic_write(&path, &json)
+}
@@ -0,0 +1,380 @@
+//! Registry of buy tasks, so front ends can list tasks and see what each
+//! one is doing without tracking it themselves.
+//!
+//! Scheduled tasks are also saved to `tasks.json` (see
+//! [`storage::save_task`](crate::storage::save_task)) until they finish, so
+//! they can be re-armed after a restart.
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::watch;
+use crate::buy::{parse_start_time, BuyOptions, TicketInfo};
+use crate::error::{Error, Result};
+use crate::http::{account_key, cookie_pairs, cookie_uid};
+use crate::logs::{LogBuffer, LogEntry, LogLevel, LogQuery};
+use crate::phase::Phase;
+use crate::storage::StoredTask;
+
+pub(crate) fn now_string() -> String {
+    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// What a task is for and how far it got.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TaskRecord {
+    pub task_id: String,
+    pub project_id: String,
+    pub project_name: Option<String>,
+    pub screen_id: String,
+    pub screen_name: Option<String>,
+    pub sku_id: String,
+    pub sku_name: Option<String>,
+    /// Buyer names
+    pub buyers: Vec<String>,
+    /// `DedeUserID` of the account the task buys with
+    pub account_uid: Option<String>,
+    pub time_start: Option<String>,
+    pub phase: Phase,
+    /// `order/prepare` requests made
+    pub prepares: u32,
+    /// `order/createV2` requests made
+    pub attempts: u32,
+    /// errno of the last create response
+    pub last_errno: Option<i64>,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    /// Result message once the task has finished
+    pub message: Option<String>,
+    /// Restored after a restart too late to start
+    #[serde(default)]
+    pub missed_start: bool,
+    /// Held by the user; `phase` is where it will continue
+    #[serde(default)]
+    pub paused: bool,
+    /// Start time moved by the user, see [`TaskHandle::reschedule`]
+    #[serde(default)]
+    pub rescheduled: bool,
+    /// Account and buyers of an exclusive task. Once one task of a group
+    /// orders, the others are stopped.
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+impl TaskRecord {
+    pub fn new(task_id: String, info: &TicketInfo, options: &BuyOptions) -> Self {
+        let time_start = options.time_start.clone().filter(|s| !s.trim().is_empty());
+        let buyers = info.buyer_info.as_array()
+            .map(|list| list.iter().filter_map(|b| b["name"].as_str()).map(|n| n.to_string()).collect())
+            .unwrap_or_default();
+        let account_uid = cookie_uid(&info.cookies);
+        let group = options.exclusive.then(|| group_key(&account_key(&cookie_pairs(&info.cookies)), &info.buyer_info));
+        Self {
+            task_id,
+            project_id: info.project_id.clone(),
+            project_name: info.project_name.clone(),
+            screen_id: info.screen_id.clone(),
+            screen_name: info.screen_name.clone(),
+            sku_id: info.sku_id.clone(),
+            sku_name: info.sku_name.clone(),
+            buyers,
+            account_uid,
+            phase: match (time_start.is_some(), options.watch) {
+                (true, _) => Phase::Scheduled,
+                (false, true) => Phase::Watching,
+                (false, false) => Phase::Preparing,
+            },
+            time_start,
+            prepares: 0,
+            attempts: 0,
+            last_errno: None,
+            started_at: now_string(),
+            finished_at: None,
+            message: None,
+            missed_start: false,
+            paused: false,
+            rescheduled: false,
+            group,
+        }
+    }
+
+    /// Record of a saved task that was not running at its start time.
+    pub fn missed(task: &StoredTask) -> Self {
+        let mut record = Self::new(task.task_id.clone(), &task.ticket_info, &task.options);
+        record.phase = Phase::Failed;
+        record.finished_at = Some(now_string());
+        record.message = Some(format!("错过开始时间 {}", record.time_start.as_deref().unwrap_or("-")));
+        record.missed_start = true;
+        record
+    }
+}
+
+/// `account:buyer,buyer` with buyer ids (names when there is no id) sorted,
+/// so the same buyers picked in a different order share a group. The
+/// account is its uid, or a digest of the cookies when they carry none.
+fn group_key(account: &str, buyer_info: &serde_json::Value) -> String {
+    let mut buyers: Vec<String> = buyer_info.as_array().into_iter().flatten()
+        .map(|b| match &b["id"] {
+            serde_json::Value::Null => b["name"].as_str().unwrap_or_default().to_string(),
+            id => id.to_string().trim_matches('"').to_string(),
+        })
+        .collect();
+    buyers.sort();
+    format!("{}:{}", account, buyers.join(","))
+}
+
+/// Split saved tasks into those still waiting for their start time and
+/// those that missed it, e.g. because the app was not running. A start
+/// time that cannot be parsed counts as missed rather than starting now.
+pub fn split_missed(tasks: Vec<StoredTask>, now: DateTime<Local>) -> (Vec<StoredTask>, Vec<StoredTask>) {
+    tasks.into_iter().partition(|t| {
+        t.options.time_start.as_deref()
+            .and_then(parse_start_time)
+            .is_some_and(|start| start > now)
+    })
+}
+
+/// Shared between a running task and the front end: the record plus the
+/// controls the front end can use.
+#[derive(Debug)]
+pub struct TaskHandle {
+    id: String,
+    stop: AtomicBool,
+    record: Mutex<TaskRecord>,
+    /// Target start time, watched by the task while it is scheduled
+    start_time: watch::Sender<Option<DateTime<Local>>>,
+    paused: watch::Sender<bool>,
+    logs: Mutex<LogBuffer>,
+    /// Tasks of the registry this task was added to, searched for its
+    /// group by [`stop_siblings`](Self::stop_siblings)
+    registry: Mutex<Weak<TaskMap>>,
+    /// Sibling whose order stopped this task
+    superseded_by: Mutex<Option<String>>,
+    /// A createV2 request is waiting for its response
+    creating: AtomicBool,
+}
+
+/// Marks a createV2 request of a task as in flight until dropped, see
+/// [`TaskHandle::order_in_flight`].
+pub struct CreateGuard<'a>(&'a TaskHandle);
+
+impl Drop for CreateGuard<'_> {
+    fn drop(&mut self) {
+        self.0.creating.store(false, Ordering::Relaxed);
+    }
+}
+
+impl TaskHandle {
+    pub fn new(record: TaskRecord) -> Arc<Self> {
+        let start_time = record.time_start.as_deref().and_then(parse_start_time);
+        Arc::new(Self {
+            id: record.task_id.clone(),
+            stop: AtomicBool::new(false),
+            record: Mutex::new(record),
+            start_time: watch::channel(start_time).0,
+            paused: watch::channel(false).0,
+            logs: Mutex::new(LogBuffer::default()),
+            registry: Mutex::new(Weak::new()),
+            superseded_by: Mutex::new(None),
+            creating: AtomicBool::new(false),
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Stop the task, also ending a pause so that it gets to stop.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.record.lock().unwrap().paused = false;
+        self.paused.send_replace(false);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Stop the unfinished tasks of this task's group, returning their ids.
+    /// They are looked up now, so tasks added or restored after this one
+    /// count too.
+    pub fn stop_siblings(&self) -> Vec<String> {
+        let Some(group) = self.record().group else {
+            return Vec::new();
+        };
+        let Some(tasks) = self.registry.lock().unwrap().upgrade() else {
+            return Vec::new();
+        };
+        let tasks = tasks.lock().unwrap();
+        tasks.values()
+            .filter(|s| s.id != self.id && !s.is_stopped())
+            .filter(|s| {
+                let record = s.record();
+                record.group.as_ref() == Some(&group) && !record.phase.is_terminal()
+            })
+            .map(|s| {
+                *s.superseded_by.lock().unwrap() = Some(self.id.clone());
+                s.stop();
+                s.id.clone()
+            })
+            .collect()
+    }
+
+    pub fn superseded_by(&self) -> Option<String> {
+        self.superseded_by.lock().unwrap().clone()
+    }
+
+    pub fn begin_create(&self) -> CreateGuard<'_> {
+        self.creating.store(true, Ordering::Relaxed);
+        CreateGuard(self)
+    }
+
+    /// A createV2 request is pending, or an order was created but its
+    /// payment and history entry are still being fetched. Stopping the task
+    /// now may lose track of a real order.
+    pub fn order_in_flight(&self) -> bool {
+        let phase = self.record().phase;
+        !phase.is_terminal() && (self.creating.load(Ordering::Relaxed) || phase == Phase::FetchingPayment)
+    }
+
+    pub fn start_time(&self) -> watch::Receiver<Option<DateTime<Local>>> {
+        self.start_time.subscribe()
+    }
+
+    /// Move the start time of a task that is still waiting for it. It is
+    /// then no longer moved along with the published sale start.
+    pub fn reschedule(&self, time_start: &str) -> Result<DateTime<Local>> {
+        self.move_start(time_start, true)
+    }
+
+    /// Follow the published sale start, see [`reschedule`](Self::reschedule).
+    pub(crate) fn follow_sale_start(&self, time_start: &str) -> Result<DateTime<Local>> {
+        self.move_start(time_start, false)
+    }
+
+    fn move_start(&self, time_start: &str, by_user: bool) -> Result<DateTime<Local>> {
+        let target = parse_start_time(time_start)
+            .ok_or_else(|| Error::InvalidInput(format!("Invalid start time: {}", time_start)))?;
+        let mut record = self.record.lock().unwrap();
+        if record.phase != Phase::Scheduled {
+            return Err(Error::InvalidInput(format!("Task {} is not waiting for its start time", self.id)));
+        }
+        record.time_start = Some(time_start.to_string());
+        record.rescheduled |= by_user;
+        self.start_time.send_replace(Some(target));
+        Ok(target)
+    }
+
+    /// Hold the task before its next request, or past its start time if it
+    /// is still waiting for it. Its phase, prepare token and device state are
+    /// kept until [`resume`](Self::resume). An order that was already
+    /// created still has its payment fetched.
+    pub fn pause(&self) -> Result<()> {
+        let mut record = self.record.lock().unwrap();
+        if record.phase.is_terminal() {
+            return Err(Error::InvalidInput(format!("Task {} has already finished", self.id)));
+        }
+        record.paused = true;
+        self.paused.send_replace(true);
+        Ok(())
+    }
+
+    pub fn resume(&self) -> Result<()> {
+        let mut record = self.record.lock().unwrap();
+        if record.phase.is_terminal() {
+            return Err(Error::InvalidInput(format!("Task {} has already finished", self.id)));
+        }
+        record.paused = false;
+        self.paused.send_replace(false);
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    pub fn paused(&self) -> watch::Receiver<bool> {
+        self.paused.subscribe()
+    }
+
+    /// Add a line to the task's log, tagged with its current phase.
+    pub fn log(&self, level: LogLevel, message: &str) -> LogEntry {
+        let phase = self.record.lock().unwrap().phase;
+        self.logs.lock().unwrap().push(level, phase, message)
+    }
+
+    pub fn logs(&self, query: &LogQuery) -> Vec<LogEntry> {
+        self.logs.lock().unwrap().query(query)
+    }
+
+    pub fn record(&self) -> TaskRecord {
+        self.record.lock().unwrap().clone()
+    }
+
+    pub fn update(&self, f: impl FnOnce(&mut TaskRecord)) {
+        f(&mut self.record.lock().unwrap());
+    }
+}
+
+/// All tasks of this process, finished ones included until removed.
+#[derive(Debug, Default)]
+pub struct TaskRegistry {
+    tasks: Arc<TaskMap>,
+}
+
+type TaskMap = Mutex<HashMap<String, Arc<TaskHandle>>>;
+
+impl TaskRegistry {
+    /// Add a task; an exclusive one stops the others of its group in here
+    /// once it orders, see [`TaskHandle::stop_siblings`].
+    pub fn insert(&self, handle: Arc<TaskHandle>) {
+        *handle.registry.lock().unwrap() = Arc::downgrade(&self.tasks);
+        self.tasks.lock().unwrap().insert(handle.id().to_string(), handle);
+    }
+
+    /// Stop every task, e.g. when the app exits.
+    pub fn stop_all(&self) {
+        for task in self.tasks.lock().unwrap().values() {
+            task.stop();
+        }
+    }
+
+    /// Tasks that may be creating or recording an order right now.
+    pub fn orders_in_flight(&self) -> Vec<TaskRecord> {
+        self.tasks.lock().unwrap().values()
+            .filter(|t| t.order_in_flight())
+            .map(|t| t.record())
+            .collect()
+    }
+
+    /// Wait up to `timeout` for every task to finish, returning those that
+    /// did not.
+    pub async fn wait_finished(&self, timeout: Duration) -> Vec<TaskRecord> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let running: Vec<TaskRecord> = self.list().into_iter().filter(|r| !r.phase.is_terminal()).collect();
+            if running.is_empty() || tokio::time::Instant::now() >= deadline {
+                return running;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    pub fn get(&self, task_id: &str) -> Option<Arc<TaskHandle>> {
+        self.tasks.lock().unwrap().get(task_id).cloned()
+    }
+
+    pub fn remove(&self, task_id: &str) -> Option<Arc<TaskHandle>> {
+        self.tasks.lock().unwrap().remove(task_id)
+    }
+
+    /// Records of all tasks, newest first.
+    pub fn list(&self) -> Vec<TaskRecord> {
+        let mut records: Vec<_> = self.tasks.lock().unwrap().values().map(|h| h.record()).collect();
+        records.sort_by(|a, b| b.started_at.cmp(&a.started_at).then_with(|| a.task_id.cmp(&b.task_id)));
+        records
+    }
+}
@@ -0,0 +1,373 @@
+//! Server time offset from several samples of an HTTP time API or an NTP
+//! server.
+//!
+//! Each [`Sample`] bounds the offset (server minus local time) by when the
+//! request was sent and the reply arrived, widened by the resolution of the
+//! server's clock. [`estimate`] drops samples delayed by congestion and
+//! intersects the bounds of the rest, so a second-resolution API such as
+//! Bilibili's `click/now` still gives an offset well below a second once
+//! the samples straddle a second boundary.
+//!
+//! Where the time comes from is a [`TimeSource`]; [`measure_first`] goes
+//! down a list of them, see [`sources`] for the default chain.
+
+use crate::config::Endpoints;
+use crate::error::{Error, Result};
+use log::warn;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::time::{sleep, timeout};
+
+/// Samples taken by [`measure`] unless told otherwise.
+pub const DEFAULT_SAMPLES: u32 = 8;
+
+/// Pause between samples. Not a divisor of a second, so the samples of a
+/// second-resolution server fall at different points of its second.
+const SAMPLE_GAP: Duration = Duration::from_millis(130);
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+const NTP_PORT: u16 = 123;
+
+/// Seconds from 1900-01-01 (NTP era 0) to the unix epoch.
+const NTP_UNIX_OFFSET: f64 = 2_208_988_800.0;
+
+/// Unit of a time read from a JSON reply. Sources without a pointer carry
+/// the default, which is not used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeUnit {
+    #[serde(rename = "s")]
+    Seconds,
+    #[default]
+    #[serde(rename = "ms")]
+    Millis,
+}
+
+impl TimeUnit {
+    fn millis(self) -> f64 {
+        match self {
+            TimeUnit::Seconds => 1000.0,
+            TimeUnit::Millis => 1.0,
+        }
+    }
+}
+
+/// A server to sync time with, as listed in
+/// [`Settings::time_sources`](crate::config::Settings::time_sources).
+///
+/// `url` starting with `http` is an HTTP time API read at `pointer` in
+/// `unit`, or from its `Date` header when `date_header` is set. With
+/// neither, the formats of the common time APIs are tried as before time
+/// sources were configurable. Anything else is an NTP host with an
+/// optional port.
+///
+/// `unit` must be given along with `pointer`; a source in seconds read as
+/// milliseconds would be off by decades.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawTimeSource")]
+pub struct TimeSource {
+    pub url: String,
+    /// JSON pointer to the time in the reply, e.g. `/data/now`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pointer: Option<String>,
+    pub unit: TimeUnit,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub date_header: bool,
+}
+
+/// [`TimeSource`] as written in the settings, before checking `unit`.
+#[derive(Deserialize)]
+struct RawTimeSource {
+    url: String,
+    #[serde(default)]
+    pointer: Option<String>,
+    #[serde(default)]
+    unit: Option<TimeUnit>,
+    #[serde(default)]
+    date_header: bool,
+}
+
+impl TryFrom<RawTimeSource> for TimeSource {
+    type Error = String;
+
+    fn try_from(raw: RawTimeSource) -> std::result::Result<Self, String> {
+        if raw.pointer.is_some() && raw.unit.is_none() {
+            return Err(format!("Time source {} needs a unit (\"s\" or \"ms\") for its pointer", raw.url));
+        }
+        Ok(Self { url: raw.url, pointer: raw.pointer, unit: raw.unit.unwrap_or_default(), date_header: raw.date_header })
+    }
+}
+
+impl TimeSource {
+    /// A URL or NTP host with the time read as described on [`TimeSource`].
+    pub fn from_url(url: &str) -> Self {
+        Self { url: url.trim().to_string(), pointer: None, unit: TimeUnit::Millis, date_header: false }
+    }
+
+    /// Bilibili's `click/now`, in seconds at `data.now`.
+    pub fn bilibili(endpoints: &Endpoints) -> Self {
+        Self { url: endpoints.click_now(), pointer: Some("/data/now".to_string()), unit: TimeUnit::Seconds, date_header: false }
+    }
+
+    fn is_http(&self) -> bool {
+        self.url.starts_with("http")
+    }
+}
+
+/// Sources to try in order: `configured`, then the legacy `ntp_server`
+/// setting, then Bilibili's `click/now`. Blank URLs and repeated sources
+/// are left out.
+pub fn sources(configured: &[TimeSource], ntp_server: Option<&str>, endpoints: &Endpoints) -> Vec<TimeSource> {
+    let legacy = ntp_server.map(TimeSource::from_url);
+    let mut chain: Vec<TimeSource> = Vec::new();
+    for source in configured.iter().cloned().chain(legacy).chain([TimeSource::bilibili(endpoints)]) {
+        if !source.url.trim().is_empty() && !chain.contains(&source) {
+            chain.push(source);
+        }
+    }
+    chain
+}
+
+/// One exchange with a time server. The offset lies within
+/// `low_ms..=high_ms`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub rtt_ms: f64,
+    pub low_ms: f64,
+    pub high_ms: f64,
+}
+
+impl Sample {
+    /// A reply stamped `server_ms`, truncated to `resolution_ms`, to a
+    /// request sent at local `sent_ms` and answered at `received_ms`.
+    pub fn http(sent_ms: f64, received_ms: f64, server_ms: f64, resolution_ms: f64) -> Self {
+        Self {
+            rtt_ms: received_ms - sent_ms,
+            low_ms: server_ms - received_ms,
+            high_ms: server_ms + resolution_ms - sent_ms,
+        }
+    }
+
+    /// An NTP exchange: sent at local `t1`, received by the server at `t2`,
+    /// answered at `t3` and received back at local `t4`, all in ms.
+    pub fn ntp(t1: f64, t2: f64, t3: f64, t4: f64) -> Self {
+        let offset = ((t2 - t1) + (t3 - t4)) / 2.0;
+        let delay = ((t4 - t1) - (t3 - t2)).max(0.0);
+        Self { rtt_ms: delay, low_ms: offset - delay / 2.0, high_ms: offset + delay / 2.0 }
+    }
+
+    /// Midpoint of the bounds, i.e. compensated by half the round trip.
+    pub fn offset_ms(&self) -> f64 {
+        (self.low_ms + self.high_ms) / 2.0
+    }
+}
+
+/// Offset of the server clock worked out from several samples.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimeSync {
+    /// Server minus local time
+    pub offset_ms: f64,
+    /// The offset is right within plus or minus this
+    pub uncertainty_ms: f64,
+    pub best_rtt_ms: f64,
+    /// Samples the offset is based on
+    pub samples: usize,
+    /// Samples dropped as delayed or inconsistent with the others
+    pub discarded: usize,
+    /// URL of the [`TimeSource`] measured, empty from [`estimate`]
+    pub source: String,
+}
+
+/// Combine `samples`; `None` when there are none.
+///
+/// Samples whose round trip is over twice the median are dropped first.
+/// The rest are intersected starting from the fastest; one whose bounds
+/// do not overlap the samples kept so far is dropped as an outlier.
+pub fn estimate(samples: &[Sample]) -> Option<TimeSync> {
+    let mut rtts: Vec<f64> = samples.iter().map(|s| s.rtt_ms).collect();
+    rtts.sort_by(f64::total_cmp);
+    let median = *rtts.get(rtts.len() / 2)?;
+
+    let mut kept: Vec<&Sample> = samples.iter().filter(|s| s.rtt_ms <= median * 2.0 + 1.0).collect();
+    kept.sort_by(|a, b| a.rtt_ms.total_cmp(&b.rtt_ms));
+    let best = kept[0];
+
+    let (mut low, mut high) = (best.low_ms, best.high_ms);
+    let mut used = 1;
+    for s in &kept[1..] {
+        let (l, h) = (low.max(s.low_ms), high.min(s.high_ms));
+        if l <= h {
+            (low, high) = (l, h);
+            used += 1;
+        }
+    }
+
+    Some(TimeSync {
+        offset_ms: (low + high) / 2.0,
+        uncertainty_ms: (high - low) / 2.0,
+        best_rtt_ms: best.rtt_ms,
+        samples: used,
+        discarded: samples.len() - used,
+        source: String::new(),
+    })
+}
+
+/// Take `count` samples of `source` and combine them with [`estimate`]. A
+/// failed first sample fails the measurement, later failed samples are
+/// skipped.
+pub async fn measure(client: &Client, source: &TimeSource, count: u32) -> Result<TimeSync> {
+    let mut samples = Vec::new();
+    for i in 0..count.max(1) {
+        if i > 0 {
+            sleep(SAMPLE_GAP).await;
+        }
+        let sample = if source.is_http() {
+            sample_http(client, source).await
+        } else {
+            sample_ntp(&source.url).await
+        };
+        match sample {
+            Ok(s) => samples.push(s),
+            Err(e) if samples.is_empty() => return Err(e),
+            Err(e) => warn!("Time sample from {} failed: {}", source.url, e),
+        }
+    }
+    let sync = estimate(&samples).ok_or_else(|| Error::Network(format!("No time samples from {}", source.url)))?;
+    Ok(TimeSync { source: source.url.clone(), ..sync })
+}
+
+/// [`measure`] the first of `sources` that answers.
+pub async fn measure_first(client: &Client, sources: &[TimeSource], count: u32) -> Result<TimeSync> {
+    let mut errors = Vec::new();
+    for source in sources {
+        match measure(client, source, count).await {
+            Ok(sync) => return Ok(sync),
+            Err(e) => {
+                warn!("Time source {} failed: {}", source.url, e);
+                errors.push(format!("{}: {}", source.url, e));
+            }
+        }
+    }
+    Err(Error::Network(format!("No time source answered ({})", errors.join("; "))))
+}
+
+/// Local wall clock in ms since the unix epoch, with sub-ms precision.
+pub fn local_ms() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64() * 1000.0
+}
+
+pub async fn sample_http(client: &Client, source: &TimeSource) -> Result<Sample> {
+    let sent = local_ms();
+    let res = client.get(&source.url).timeout(REQUEST_TIMEOUT).send().await?;
+    if source.date_header {
+        let received = local_ms();
+        let date = res.headers().get(reqwest::header::DATE).and_then(|d| d.to_str().ok())
+            .ok_or_else(|| Error::InvalidResponse(format!("No Date header from {}", source.url)))?;
+        let server = chrono::DateTime::parse_from_rfc2822(date)
+            .map_err(|e| Error::InvalidResponse(format!("Invalid Date header {:?}: {}", date, e)))?;
+        return Ok(Sample::http(sent, received, server.timestamp_millis() as f64, 1000.0));
+    }
+
+    let res: Value = res.json().await?;
+    let received = local_ms();
+    let (server, resolution) = match &source.pointer {
+        Some(pointer) => {
+            let value = res.pointer(pointer)
+                .and_then(|v| v.as_f64().or_else(|| v.as_str().and_then(|s| s.trim().parse().ok())))
+                .ok_or_else(|| Error::InvalidResponse(format!("No time at {} in the reply of {}", pointer, source.url)))?;
+            (value * source.unit.millis(), source.unit.millis())
+        }
+        None => parse_server_time(&res)
+            .map(|(time, resolution)| (time as f64, resolution as f64))
+            .ok_or_else(|| Error::InvalidResponse("Failed to parse server time from response".to_string()))?,
+    };
+    Ok(Sample::http(sent, received, server, resolution))
+}
+
+/// Server time in ms and its resolution in ms, from the formats of the
+/// common time APIs. Used by sources without a `pointer`.
+fn parse_server_time(res: &Value) -> Option<(i64, i64)> {
+    // Bilibili: {"data": {"now": 169...}} in seconds
+    if let Some(now) = res["data"]["now"].as_i64() {
+        return Some((now * 1000, 1000));
+    }
+    // Taobao: {"data": {"t": "169..."}} in ms
+    if let Some(t) = res["data"]["t"].as_str().and_then(|t| t.parse::<i64>().ok()) {
+        return Some((t, 1));
+    }
+    // JD: {"serverTime": 169...} in ms
+    if let Some(t) = res["serverTime"].as_i64() {
+        return Some((t, 1));
+    }
+    // Generic "time": seconds or ms by magnitude (2023 is ~1.7e9 s, ~1.7e12 ms)
+    if let Some(t) = res["time"].as_i64() {
+        return Some(if t > 100_000_000_000 { (t, 1) } else { (t * 1000, 1000) });
+    }
+    None
+}
+
+/// `host:port` of an NTP `server` given as a host, an IP address (IPv6
+/// with or without brackets) or either with a port, port 123 by default.
+fn ntp_address(server: &str) -> String {
+    if server.parse::<SocketAddr>().is_ok() {
+        return server.to_string();
+    }
+    if let Ok(ip) = server.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        return SocketAddr::new(ip, NTP_PORT).to_string();
+    }
+    match server.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') && port.parse::<u16>().is_ok() => server.to_string(),
+        _ => format!("{}:{}", server, NTP_PORT),
+    }
+}
+
+/// One SNTP exchange (RFC 4330) over an async UDP socket.
+pub async fn sample_ntp(server: &str) -> Result<Sample> {
+    let address = ntp_address(server);
+    let peer = lookup_host(&address).await
+        .map_err(|e| Error::Network(format!("NTP server {}: {}", address, e)))?
+        .next()
+        .ok_or_else(|| Error::Network(format!("NTP server {} has no address", address)))?;
+    let local: SocketAddr = if peer.is_ipv6() { (Ipv6Addr::UNSPECIFIED, 0).into() } else { (Ipv4Addr::UNSPECIFIED, 0).into() };
+    let socket = UdpSocket::bind(local).await
+        .map_err(|e| Error::Network(format!("UDP Bind Error: {}", e)))?;
+    socket.connect(peer).await
+        .map_err(|e| Error::Network(format!("NTP server {}: {}", address, e)))?;
+
+    // LI 0, version 4, mode 3 (client); the transmit timestamp comes back
+    // as the originate timestamp and identifies the reply
+    let mut request = [0u8; 48];
+    request[0] = 0x23;
+    let t1 = local_ms();
+    request[40..48].copy_from_slice(&to_ntp(t1).to_be_bytes());
+    socket.send(&request).await.map_err(|e| Error::Network(format!("NTP Error: {}", e)))?;
+
+    let mut reply = [0u8; 48];
+    let n = timeout(Duration::from_secs(2), socket.recv(&mut reply)).await
+        .map_err(|_| Error::Network(format!("NTP server {} did not answer", address)))?
+        .map_err(|e| Error::Network(format!("NTP Error: {}", e)))?;
+    let t4 = local_ms();
+
+    if n < 48 || reply[0] & 0x07 != 4 || reply[24..32] != request[40..48] {
+        return Err(Error::InvalidResponse(format!("Unexpected NTP reply from {}", address)));
+    }
+    if reply[1] == 0 {
+        return Err(Error::Network(format!("NTP server {} refused the request", address)));
+    }
+    let timestamp = |i: usize| from_ntp(u64::from_be_bytes(reply[i..i + 8].try_into().unwrap()));
+    Ok(Sample::ntp(t1, timestamp(32), timestamp(40), t4))
+}
+
+fn to_ntp(unix_ms: f64) -> u64 {
+    let secs = unix_ms / 1000.0 + NTP_UNIX_OFFSET;
+    ((secs.trunc() as u64) << 32) | (secs.fract() * 4_294_967_296.0) as u64
+}
+
+fn from_ntp(ntp: u64) -> f64 {
+    let secs = (ntp >> 32) as f64 + (ntp & 0xffff_ffff) as f64 / 4_294_967_296.0;
+    (secs - NTP_UNIX_OFFSET) * 1000.0
+}
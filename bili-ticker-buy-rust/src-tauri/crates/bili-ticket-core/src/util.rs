@@ -49,6 +49,11 @@ impl CTokenGenerator {
         }
     }
 
+    /// Server minus local time in seconds, for tokens generated from now on
+    pub fn set_time_offset(&mut self, time_offset: i64) {
+        self.time_offset = time_offset;
+    }
+
     pub fn generate_ctoken(&mut self, is_create_v2: bool) -> String {
         let mut rng = rand::thread_rng();
         self.touch_event = 255;
@@ -63,7 +68,9 @@ impl CTokenGenerator {
 
         if is_create_v2 {
             let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
-            self.time_difference = (now + self.time_offset - self.ticket_collection_t as i64) as u32;
+            // A smaller offset than the one the collection time was taken
+            // with would put it in the future
+            self.time_difference = (now + self.time_offset - self.ticket_collection_t as i64).max(0) as u32;
             self.timer = (self.time_difference as u64 + self.stay_time) as u32;
             self.page_unload = 25;
         } else {
@@ -0,0 +1,27 @@
+use bili_ticket_core::clock::{Clock, ClockState};
+
+#[tokio::test]
+async fn set_offset_reaches_every_subscriber() {
+    let clock = Clock::new(reqwest::Client::new(), Vec::new());
+    let mut first = clock.subscribe();
+    let mut second = clock.clone().subscribe();
+    assert_eq!(clock.offset_ms(), None);
+
+    clock.set_offset(1500);
+    for rx in [&mut first, &mut second] {
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(*rx.borrow_and_update(), ClockState { offset_ms: Some(1500), sync: None });
+    }
+}
+
+#[tokio::test]
+async fn failed_sync_keeps_the_last_offset() {
+    let clock = Clock::new(reqwest::Client::new(), Vec::new());
+    clock.set_offset(-250);
+    let rx = clock.subscribe();
+
+    let err = clock.sync().await.unwrap_err();
+    assert!(err.to_string().contains("No time source answered"), "{}", err);
+    assert!(!rx.has_changed().unwrap());
+    assert_eq!(clock.offset_ms(), Some(-250));
+}
@@ -0,0 +1,98 @@
+use bili_ticket_core::error::Result;
+use bili_ticket_core::event::{EventSink, FinishReason, TaskEvent};
+use bili_ticket_core::phase::Phase;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+const SCHEMA: &str = include_str!("../../../../docs/events.schema.json");
+
+#[derive(Default)]
+struct CollectSink(Mutex<Vec<(String, Value)>>);
+
+impl EventSink for CollectSink {
+    fn emit(&self, event: &str, payload: Value) -> Result<()> {
+        self.0.lock().unwrap().push((event.to_string(), payload));
+        Ok(())
+    }
+}
+
+fn samples() -> Vec<TaskEvent> {
+    vec![
+        TaskEvent::TaskScheduled { time_start: "2025-07-01 20:00:00".to_string(), offset_ms: -35 },
+        TaskEvent::Countdown { remaining_ms: 5000, offset_ms: -35 },
+        TaskEvent::TaskRescheduled { time_start: "2025-07-01 20:30:00".to_string() },
+        TaskEvent::TaskPhase { phase: Phase::FetchingPayment, attempt: 3 },
+        TaskEvent::TaskPaused { paused: true, phase: Phase::Creating },
+        TaskEvent::Restocked { screen_id: "1001".to_string(), sku_id: "2002".to_string(), sale_flag: Some(2) },
+        TaskEvent::PrepareResult { errno: None, latency_ms: 120, message: "timed out".to_string() },
+        TaskEvent::AttemptResult {
+            attempt: 7,
+            round_attempt: 2,
+            round_size: 60,
+            errno: Some(900001),
+            latency_ms: 85,
+            message: "当前拥挤，请稍后再试".to_string(),
+        },
+        TaskEvent::SkuSwitched { errno: 100009, screen_id: "1002".to_string(), sku_id: "2003".to_string(), sku_name: None, pay_money: 76000 },
+        TaskEvent::OrderCreated { order_id: None, price: Some(39000) },
+        TaskEvent::PaymentReady { order_id: "1".to_string(), url: "https://pay".to_string(), price: None },
+        TaskEvent::TaskFinished { reason: FinishReason::AttemptsExhausted, success: false, message: String::new() },
+    ]
+}
+
+fn strings(value: &Value) -> BTreeSet<String> {
+    value.as_array().unwrap().iter().map(|v| v.as_str().unwrap().to_string()).collect()
+}
+
+#[test]
+fn payloads_carry_task_id_and_parse_back() {
+    let sink = CollectSink::default();
+    for event in samples() {
+        event.emit(&sink, "task-1").unwrap();
+    }
+    let emitted = sink.0.lock().unwrap();
+    for ((name, payload), event) in emitted.iter().zip(samples()) {
+        assert_eq!(name, event.name());
+        assert_eq!(payload["event"], event.name());
+        assert_eq!(payload["task_id"], "task-1");
+        assert_eq!(serde_json::from_value::<TaskEvent>(payload.clone()).unwrap(), event);
+    }
+}
+
+#[test]
+fn schema_describes_every_event() {
+    let schema: Value = serde_json::from_str(SCHEMA).unwrap();
+    let defs = &schema["$defs"];
+    let sink = CollectSink::default();
+    for event in samples() {
+        event.emit(&sink, "task-1").unwrap();
+    }
+
+    for (name, payload) in sink.0.lock().unwrap().iter() {
+        let def = &defs[name.as_str()];
+        assert_eq!(def["properties"]["event"]["const"], name.as_str(), "no schema for {}", name);
+        let keys: BTreeSet<String> = payload.as_object().unwrap().keys().cloned().collect();
+        assert_eq!(strings(&def["required"]), keys, "fields of {}", name);
+        let properties: BTreeSet<String> = def["properties"].as_object().unwrap().keys().cloned().collect();
+        assert_eq!(properties, keys, "properties of {}", name);
+    }
+    assert_eq!(schema["oneOf"].as_array().unwrap().len(), samples().len());
+
+    let phases = [Phase::Scheduled, Phase::Watching, Phase::Preparing, Phase::Creating, Phase::FetchingPayment, Phase::Done, Phase::Failed, Phase::Stopped];
+    let phase_names: BTreeSet<String> = phases.iter().map(|p| serde_json::to_value(p).unwrap().as_str().unwrap().to_string()).collect();
+    assert_eq!(strings(&defs["phase"]["enum"]), phase_names);
+
+    let reasons = [
+        FinishReason::OrderCreated,
+        FinishReason::AttemptsExhausted,
+        FinishReason::Stopped,
+        FinishReason::SiblingSucceeded,
+        FinishReason::DeadlineReached,
+        FinishReason::PrepareFailures,
+        FinishReason::RequestLimit,
+        FinishReason::TerminalErrno,
+    ];
+    let reason_names: BTreeSet<String> = reasons.iter().map(|r| serde_json::to_value(r).unwrap().as_str().unwrap().to_string()).collect();
+    assert_eq!(strings(&defs["task_finished"]["properties"]["reason"]["enum"]), reason_names);
+}
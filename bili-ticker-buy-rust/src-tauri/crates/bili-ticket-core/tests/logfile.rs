@@ -0,0 +1,46 @@
+use bili_ticket_core::config::LogSettings;
+use bili_ticket_core::logfile::FileLogger;
+use bili_ticket_core::logs::LogLevel;
+use chrono::Local;
+use log::{Level, Log, Record};
+use std::fs;
+
+fn log(logger: &FileLogger, target: &str, level: Level, message: &str) {
+    logger.log(&Record::builder().target(target).level(level).args(format_args!("{}", message)).build());
+}
+
+fn names(logger: &FileLogger) -> Vec<String> {
+    logger.files().iter().map(|p| p.file_name().unwrap().to_string_lossy().into_owned()).collect()
+}
+
+#[test]
+fn files_roll_over_by_size_and_old_ones_are_pruned() {
+    log::set_max_level(log::LevelFilter::Info);
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("bili-ticket-2020-01-01.log"), "old\n").unwrap();
+    fs::write(dir.path().join("bili-ticket-2020-01-02.log"), "old\n").unwrap();
+    fs::write(dir.path().join("notes.txt"), "not a log file\n").unwrap();
+    let settings = LogSettings { level: LogLevel::Info, max_file_mb: 1, max_files: 2 };
+    let logger = FileLogger::new(dir.path(), &settings).unwrap();
+    let today = Local::now().format("%Y-%m-%d").to_string();
+
+    log(&logger, "bili_ticket_core::buy", Level::Info, "[task a] Creating order");
+    log(&logger, "bili_ticket_core::buy", Level::Debug, "below the level");
+    log(&logger, "reqwest::connect", Level::Info, "dependency chatter");
+    log(&logger, "reqwest::connect", Level::Warn, "dependency warning");
+    assert_eq!(names(&logger), ["bili-ticket-2020-01-02.log".to_string(), format!("bili-ticket-{}.log", today)]);
+
+    let content = fs::read_to_string(dir.path().join(format!("bili-ticket-{}.log", today))).unwrap();
+    let lines: Vec<_> = content.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with("INFO  bili_ticket_core::buy [task a] Creating order"));
+    assert!(lines[1].ends_with("WARN  reqwest::connect dependency warning"));
+
+    let line = "x".repeat(1000);
+    for _ in 0..1100 {
+        log(&logger, "bili_ticket_core::buy", Level::Info, &line);
+    }
+    assert_eq!(names(&logger), [format!("bili-ticket-{}.log", today), format!("bili-ticket-{}.1.log", today)]);
+    assert!(fs::metadata(dir.path().join(format!("bili-ticket-{}.log", today))).unwrap().len() <= 1024 * 1024);
+    assert!(dir.path().join("notes.txt").exists());
+}
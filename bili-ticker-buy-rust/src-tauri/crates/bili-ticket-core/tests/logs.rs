@@ -0,0 +1,60 @@
+use bili_ticket_core::logs::{export, ExportFormat, LogBuffer, LogEntry, LogLevel, LogQuery};
+use bili_ticket_core::phase::Phase;
+
+fn buffer() -> LogBuffer {
+    let mut logs = LogBuffer::new(100);
+    logs.push(LogLevel::Info, Phase::Scheduled, "Waiting");
+    logs.push(LogLevel::Debug, Phase::Preparing, "Prepare result");
+    logs.push(LogLevel::Warn, Phase::Preparing, "Prepare failed");
+    logs.push(LogLevel::Info, Phase::Creating, "Creating order");
+    logs.push(LogLevel::Error, Phase::Creating, "Request error");
+    logs
+}
+
+fn messages(entries: &[LogEntry]) -> Vec<&str> {
+    entries.iter().map(|e| e.message.as_str()).collect()
+}
+
+#[test]
+fn oldest_lines_are_dropped_when_full() {
+    let mut logs = LogBuffer::new(3);
+    for i in 1..=5 {
+        logs.push(LogLevel::Info, Phase::Creating, &format!("line {}", i));
+    }
+    let entries = logs.query(&LogQuery::default());
+    assert_eq!(messages(&entries), ["line 3", "line 4", "line 5"]);
+    assert_eq!(entries.iter().map(|e| e.seq).collect::<Vec<_>>(), [3, 4, 5]);
+}
+
+#[test]
+fn query_filters_by_seq_level_and_phase() {
+    let logs = buffer();
+
+    let after = logs.query(&LogQuery { since: Some(3), ..Default::default() });
+    assert_eq!(messages(&after), ["Creating order", "Request error"]);
+
+    let limited = logs.query(&LogQuery { since: Some(1), limit: Some(2), ..Default::default() });
+    assert_eq!(messages(&limited), ["Prepare result", "Prepare failed"]);
+
+    let warnings = logs.query(&LogQuery { level: Some(LogLevel::Warn), ..Default::default() });
+    assert_eq!(messages(&warnings), ["Prepare failed", "Request error"]);
+
+    let preparing = logs.query(&LogQuery { phase: Some(Phase::Preparing), level: Some(LogLevel::Info), ..Default::default() });
+    assert_eq!(messages(&preparing), ["Prepare failed"]);
+}
+
+#[test]
+fn export_as_text_and_json() {
+    let entries = buffer().query(&LogQuery { since: Some(3), ..Default::default() });
+
+    let text = export(&entries, ExportFormat::Text).unwrap();
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with("INFO  [creating] Creating order"));
+    assert!(lines[1].ends_with("ERROR [creating] Request error"));
+
+    let json: serde_json::Value = serde_json::from_str(&export(&entries, ExportFormat::Json).unwrap()).unwrap();
+    assert_eq!(json[1]["level"], "error");
+    assert_eq!(json[1]["phase"], "creating");
+    assert_eq!(json[1]["seq"], 5);
+}
@@ -0,0 +1,91 @@
+use bili_ticket_core::error::ErrorKind;
+use bili_ticket_core::models::{ApiResponse, BuyerList, Project, UserNav};
+use chrono::{Local, TimeZone};
+use serde_json::json;
+
+#[test]
+fn numeric_strings_and_int_flags_are_accepted() {
+    let res: ApiResponse<Project> = ApiResponse::parse(json!({
+        "errno": 0,
+        "data": {
+            "id": "85939",
+            "name": "BW",
+            "has_eticket": 1,
+            "screen_list": [{ "id": 1, "name": "Day 1", "express_fee": "1000", "ticket_list": [{ "id": "2", "desc": "A", "price": "38000" }] }]
+        }
+    }), "project/getV2").unwrap();
+
+    let project = res.into_data().unwrap();
+    assert_eq!(project.id, 85939);
+    assert!(project.has_eticket);
+    assert_eq!(project.screen_list[0].express_fee, 1000);
+    assert_eq!(project.screen_list[0].ticket_list[0].price, 38000);
+}
+
+#[test]
+fn sku_stock_comes_from_clickable_and_sale_flag() {
+    let project: Project = serde_json::from_value(json!({
+        "id": 1,
+        "screen_list": [{ "id": 10, "ticket_list": [
+            { "id": 20, "price": 100, "clickable": true, "sale_flag": { "number": 2, "display_name": "预售中" } },
+            { "id": 21, "price": 100, "clickable": false, "sale_flag": { "number": 4 } },
+            { "id": 22, "price": 100, "clickable": true, "sale_flag_number": 8 },
+            { "id": 23, "price": 100, "sale_flag_number": 6 },
+            { "id": 24, "price": 100 }
+        ] }]
+    })).unwrap();
+
+    let purchasable: Vec<_> = ["20", "21", "22", "23", "24"].iter()
+        .map(|id| project.sku("10", id).unwrap().purchasable())
+        .collect();
+    assert_eq!(purchasable, [true, false, false, true, true]);
+    assert_eq!(project.sku("10", "22").unwrap().sale_flag(), Some(8));
+    assert!(project.sku("11", "20").is_none());
+}
+
+#[test]
+fn sale_start_comes_from_the_sku_or_its_screen() {
+    let project: Project = serde_json::from_value(json!({
+        "id": 1,
+        "screen_list": [
+            { "id": 10, "saleStart": 1751371200, "ticket_list": [
+                { "id": 20, "price": 100, "saleStart": 1751374800 },
+                { "id": 21, "price": 100, "sale_start": "2025-07-01 20:30:00" },
+                { "id": 22, "price": 100 }
+            ] },
+            { "id": 11, "saleStart": 0, "ticket_list": [{ "id": 23, "price": 100, "saleStart": 0 }] }
+        ]
+    })).unwrap();
+
+    assert_eq!(project.sale_start("10", "20"), Local.timestamp_opt(1751374800, 0).single());
+    assert_eq!(project.sale_start("10", "21"), Local.with_ymd_and_hms(2025, 7, 1, 20, 30, 0).single());
+    assert_eq!(project.sale_start("10", "22"), Local.timestamp_opt(1751371200, 0).single());
+    assert_eq!(project.sale_start("11", "23"), None);
+}
+
+#[test]
+fn missing_required_field_names_endpoint_and_field() {
+    let err = ApiResponse::<UserNav>::parse(json!({ "code": 0, "data": { "uname": "x" } }), "x/web-interface/nav").unwrap_err();
+    let msg = err.to_string();
+    assert!(msg.contains("x/web-interface/nav"), "{}", msg);
+    assert!(msg.contains("mid"), "{}", msg);
+}
+
+#[test]
+fn data_of_failed_call_is_not_validated() {
+    let res = ApiResponse::<UserNav>::parse(json!({ "code": -101, "message": "账号未登录", "data": { "isLogin": false } }), "nav").unwrap();
+    assert!(!res.is_ok());
+    assert!(res.data.is_none());
+    let err = res.into_data().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Auth);
+    assert_eq!(err.code(), Some(-101));
+    assert_eq!(serde_json::to_value(&err).unwrap(), json!({ "kind": "auth", "code": -101, "message": "账号未登录" }));
+}
+
+#[test]
+fn failed_ticket_call_keeps_errno() {
+    let res = ApiResponse::<BuyerList>::parse(json!({ "errno": 100001, "msg": "前方拥堵", "data": {} }), "buyer list").unwrap();
+    let err = res.into_data().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Api);
+    assert_eq!(serde_json::to_value(&err).unwrap(), json!({ "kind": "api", "code": 100001, "message": "API error 100001: 前方拥堵" }));
+}
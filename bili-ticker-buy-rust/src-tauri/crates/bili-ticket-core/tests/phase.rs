@@ -0,0 +1,190 @@
+use bili_ticket_core::phase::{Outcome, Phase, PhaseMachine};
+
+fn creating(finite: bool, total_attempts: u32) -> PhaseMachine {
+    let mut m = PhaseMachine::new(false, finite, total_attempts);
+    assert_eq!(m.on(Outcome::Prepared), Phase::Creating);
+    m
+}
+
+#[test]
+fn starts_scheduled_only_with_a_start_time() {
+    assert_eq!(PhaseMachine::new(true, false, 10).phase(), Phase::Scheduled);
+    assert_eq!(PhaseMachine::new(false, false, 10).phase(), Phase::Preparing);
+}
+
+#[test]
+fn scheduled_moves_to_preparing_when_start_time_is_reached() {
+    let mut m = PhaseMachine::new(true, false, 10);
+    assert_eq!(m.on(Outcome::StartTimeReached), Phase::Preparing);
+}
+
+#[test]
+fn preparing_retries_until_prepared() {
+    let mut m = PhaseMachine::new(false, false, 1);
+    assert_eq!(m.on(Outcome::PrepareFailed), Phase::Preparing);
+    assert_eq!(m.on(Outcome::PrepareFailed), Phase::Preparing);
+    assert_eq!(m.on(Outcome::Prepared), Phase::Creating);
+    assert_eq!(m.attempt(), 0);
+}
+
+#[test]
+fn failed_prepares_use_up_rounds_in_finite_mode() {
+    let mut m = PhaseMachine::new(false, true, 2);
+    assert_eq!(m.on(Outcome::PrepareFailed), Phase::Preparing);
+    assert_eq!(m.on(Outcome::PrepareFailed), Phase::Failed);
+}
+
+#[test]
+fn only_failed_prepares_use_up_a_finite_round() {
+    // Neither a switched SKU nor selling out in watch mode costs a round
+    let mut m = PhaseMachine::new(false, true, 1).with_watch(true);
+    m.on(Outcome::Restocked);
+    assert_eq!(m.on(Outcome::SkuSwitched), Phase::Preparing);
+    assert_eq!(m.on(Outcome::SkuSwitched), Phase::Preparing);
+    assert_eq!(m.on(Outcome::SoldOut), Phase::Watching);
+    m.on(Outcome::Restocked);
+    assert_eq!(m.on(Outcome::Prepared), Phase::Creating);
+
+    let mut m = PhaseMachine::new(false, true, 1);
+    assert_eq!(m.on(Outcome::SkuSwitched), Phase::Preparing);
+    assert_eq!(m.on(Outcome::PrepareFailed), Phase::Failed);
+
+    // A stop condition met at prepare ends the task, whatever rounds are left
+    let mut m = PhaseMachine::new(false, true, 3);
+    assert_eq!(m.on(Outcome::GiveUp), Phase::Failed);
+}
+
+#[test]
+fn create_failures_stay_in_creating_until_round_is_used_up() {
+    let mut m = creating(false, 3);
+    assert_eq!(m.on(Outcome::CreateFailed), Phase::Creating);
+    assert_eq!(m.on(Outcome::CreateFailed), Phase::Creating);
+    assert_eq!(m.attempt(), 2);
+    assert_eq!(m.on(Outcome::CreateFailed), Phase::Preparing);
+    // A new prepare starts a new round
+    assert_eq!(m.on(Outcome::Prepared), Phase::Creating);
+    assert_eq!(m.attempt(), 0);
+}
+
+#[test]
+fn expired_token_goes_back_to_preparing() {
+    let mut m = creating(false, 10);
+    assert_eq!(m.on(Outcome::TokenExpired), Phase::Preparing);
+}
+
+#[test]
+fn switched_sku_prepares_again_without_using_a_round() {
+    let mut m = creating(true, 1);
+    assert_eq!(m.on(Outcome::SkuSwitched), Phase::Preparing);
+    assert_eq!(m.on(Outcome::Prepared), Phase::Creating);
+    assert_eq!(m.on(Outcome::CreateFailed), Phase::Failed);
+}
+
+#[test]
+fn watch_mode_polls_until_restocked_and_after_selling_out() {
+    let mut m = PhaseMachine::new(false, false, 10).with_watch(true);
+    assert_eq!(m.phase(), Phase::Watching);
+    assert_eq!(m.on(Outcome::NotOnSale), Phase::Watching);
+    assert_eq!(m.on(Outcome::Restocked), Phase::Preparing);
+    assert_eq!(m.on(Outcome::SoldOut), Phase::Watching);
+    m.on(Outcome::Restocked);
+    m.on(Outcome::Prepared);
+    assert_eq!(m.on(Outcome::SoldOut), Phase::Watching);
+
+    let mut m = PhaseMachine::new(true, false, 10).with_watch(true);
+    assert_eq!(m.on(Outcome::StartTimeReached), Phase::Watching);
+
+    // Without watch mode the runner never reports SoldOut
+    let mut m = creating(false, 10);
+    assert_eq!(m.on(Outcome::SoldOut), Phase::Creating);
+}
+
+#[test]
+fn giving_up_fails_unless_an_order_exists() {
+    let mut m = PhaseMachine::new(true, false, 10);
+    assert_eq!(m.on(Outcome::GiveUp), Phase::Failed);
+    assert_eq!(m.on(Outcome::Stop), Phase::Failed);
+
+    let mut m = creating(false, 10);
+    assert_eq!(m.on(Outcome::GiveUp), Phase::Failed);
+
+    let mut m = creating(false, 10);
+    assert_eq!(m.on(Outcome::Created { has_order: true }), Phase::FetchingPayment);
+    assert_eq!(m.on(Outcome::GiveUp), Phase::FetchingPayment);
+}
+
+#[test]
+fn finite_mode_fails_after_last_round() {
+    let mut m = creating(true, 2);
+    assert_eq!(m.on(Outcome::TokenExpired), Phase::Preparing);
+    m.on(Outcome::Prepared);
+    assert_eq!(m.on(Outcome::CreateFailed), Phase::Creating);
+    assert_eq!(m.on(Outcome::CreateFailed), Phase::Failed);
+}
+
+#[test]
+fn infinite_mode_never_runs_out_of_rounds() {
+    let mut m = creating(false, 1);
+    for _ in 0..100 {
+        assert_eq!(m.on(Outcome::CreateFailed), Phase::Preparing);
+        assert_eq!(m.on(Outcome::Prepared), Phase::Creating);
+    }
+}
+
+#[test]
+fn zero_attempts_means_sixty_per_round() {
+    assert_eq!(PhaseMachine::new(false, false, 0).attempts_per_round(), 60);
+}
+
+#[test]
+fn created_with_order_fetches_payment() {
+    let mut m = creating(false, 10);
+    assert_eq!(m.on(Outcome::Created { has_order: true }), Phase::FetchingPayment);
+    assert_eq!(m.on(Outcome::PaymentReady), Phase::Done);
+
+    let mut m = creating(false, 10);
+    m.on(Outcome::Created { has_order: true });
+    assert_eq!(m.on(Outcome::PaymentFailed), Phase::Done);
+}
+
+#[test]
+fn created_without_order_is_done() {
+    let mut m = creating(false, 10);
+    assert_eq!(m.on(Outcome::Created { has_order: false }), Phase::Done);
+}
+
+#[test]
+fn stop_ends_every_running_phase() {
+    let mut scheduled = PhaseMachine::new(true, false, 10);
+    let mut preparing = PhaseMachine::new(false, false, 10);
+    let mut submitting = creating(false, 10);
+    let mut paying = creating(false, 10);
+    paying.on(Outcome::Created { has_order: true });
+
+    for m in [&mut scheduled, &mut preparing, &mut submitting, &mut paying] {
+        assert_eq!(m.on(Outcome::Stop), Phase::Stopped);
+    }
+}
+
+#[test]
+fn terminal_phases_are_final() {
+    let mut m = creating(false, 10);
+    m.on(Outcome::Created { has_order: false });
+    assert_eq!(m.on(Outcome::Stop), Phase::Done);
+    assert_eq!(m.on(Outcome::Prepared), Phase::Done);
+
+    let mut m = PhaseMachine::new(false, false, 10);
+    m.on(Outcome::Stop);
+    assert_eq!(m.on(Outcome::StartTimeReached), Phase::Stopped);
+}
+
+#[test]
+fn outcomes_of_other_phases_are_ignored() {
+    let mut m = PhaseMachine::new(true, false, 10);
+    assert_eq!(m.on(Outcome::Prepared), Phase::Scheduled);
+    assert_eq!(m.on(Outcome::CreateFailed), Phase::Scheduled);
+
+    let mut m = creating(false, 10);
+    assert_eq!(m.on(Outcome::PaymentReady), Phase::Creating);
+    assert_eq!(m.attempt(), 0);
+}
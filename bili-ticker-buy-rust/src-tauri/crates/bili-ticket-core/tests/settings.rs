@@ -0,0 +1,64 @@
+use bili_ticket_core::config::{Settings, SETTINGS_VERSION};
+use serde_json::json;
+use std::fs;
+
+#[test]
+fn missing_file_gives_defaults() {
+    let dir = tempfile::tempdir().unwrap();
+    let settings = Settings::load(dir.path()).unwrap();
+    assert_eq!(settings, Settings::default());
+    assert_eq!(settings.buy.interval, 1000);
+    assert_eq!(settings.buy.total_attempts, 10);
+}
+
+#[test]
+fn unversioned_file_is_migrated() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("settings.json"), r#"{ "proxy": "http://127.0.0.1:7890", "buy": { "interval": 300 } }"#).unwrap();
+
+    let settings = Settings::load(dir.path()).unwrap();
+    assert_eq!(settings.version, SETTINGS_VERSION);
+    assert_eq!(settings.proxy.as_deref(), Some("http://127.0.0.1:7890"));
+    assert_eq!(settings.buy.interval, 300);
+    // Fields missing from the file keep their defaults
+    assert_eq!(settings.buy.total_attempts, 10);
+}
+
+#[test]
+fn version_1_files_sync_by_default() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.json");
+    fs::write(&path, r#"{ "version": 1, "sync_interval": 0 }"#).unwrap();
+    assert_eq!(Settings::load(dir.path()).unwrap().sync_interval, 10_000);
+
+    // Turning it off is kept from version 2 on
+    fs::write(&path, r#"{ "version": 2, "sync_interval": 0 }"#).unwrap();
+    assert_eq!(Settings::load(dir.path()).unwrap().sync_interval, 0);
+}
+
+#[test]
+fn patch_merges_nested_objects_and_round_trips() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut settings = Settings::default();
+    settings.notifications.bark = "bark-key".to_string();
+
+    let updated = settings.merged(json!({
+        "notifications": { "ntfy": "topic" },
+        "recent_inputs": ["85939"],
+        "buy": { "mode": 1 }
+    })).unwrap();
+    assert_eq!(updated.notifications.bark, "bark-key");
+    assert_eq!(updated.notifications.ntfy, "topic");
+    assert_eq!(updated.recent_inputs, ["85939"]);
+    assert_eq!(updated.buy.mode, 1);
+    assert_eq!(updated.buy.interval, 1000);
+
+    updated.save(dir.path()).unwrap();
+    assert_eq!(Settings::load(dir.path()).unwrap(), updated);
+}
+
+#[test]
+fn invalid_patch_is_rejected() {
+    let err = Settings::default().merged(json!({ "sync_interval": "soon" })).unwrap_err();
+    assert_eq!(err.kind(), bili_ticket_core::error::ErrorKind::InvalidInput);
+}
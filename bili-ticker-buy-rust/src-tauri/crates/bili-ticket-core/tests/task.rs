@@ -0,0 +1,234 @@
+use bili_ticket_core::buy::{BuyOptions, TicketInfo};
+use bili_ticket_core::config::Settings;
+use bili_ticket_core::error::ErrorKind;
+use bili_ticket_core::phase::Phase;
+use bili_ticket_core::storage::{self, StoredTask};
+use bili_ticket_core::task::{split_missed, TaskHandle, TaskRecord, TaskRegistry};
+use chrono::{Local, TimeZone};
+use serde_json::json;
+
+fn ticket_info() -> TicketInfo {
+    serde_json::from_value(json!({
+        "project_id": "85939",
+        "project_name": "Mock Festival 2025",
+        "screen_id": "1001",
+        "screen_name": "Day 1",
+        "sku_id": "2001",
+        "count": 2,
+        "buyer_info": [{ "id": 1, "name": "张三" }, { "id": 2, "name": "李四" }],
+        "deliver_info": {},
+        "cookies": ["SESSDATA=abc; Path=/; HttpOnly", "DedeUserID=10001; Path=/"]
+    }))
+    .unwrap()
+}
+
+fn record(task_id: &str, time_start: Option<&str>) -> TaskRecord {
+    let options = BuyOptions {
+        time_start: time_start.map(|s| s.to_string()),
+        ..BuyOptions::from_settings(&Settings::default())
+    };
+    TaskRecord::new(task_id.to_string(), &ticket_info(), &options)
+}
+
+#[test]
+fn record_describes_the_task() {
+    let r = record("a", Some("2025-07-01 20:00:00"));
+    assert_eq!(r.account_uid.as_deref(), Some("10001"));
+    assert_eq!(r.buyers, ["张三", "李四"]);
+    assert_eq!(r.screen_name.as_deref(), Some("Day 1"));
+    assert_eq!(r.phase, Phase::Scheduled);
+    assert_eq!((r.prepares, r.attempts, r.last_errno), (0, 0, None));
+
+    assert_eq!(record("b", Some(" ")).phase, Phase::Preparing);
+}
+
+#[test]
+fn registry_lists_gets_and_removes_tasks() {
+    let registry = TaskRegistry::default();
+    let a = TaskHandle::new(record("a", None));
+    registry.insert(a.clone());
+    registry.insert(TaskHandle::new(record("b", None)));
+
+    a.update(|r| r.attempts = 3);
+    assert_eq!(registry.get("a").unwrap().record().attempts, 3);
+    assert_eq!(registry.list().len(), 2);
+
+    registry.get("a").unwrap().stop();
+    assert!(a.is_stopped());
+
+    assert!(registry.remove("a").is_some());
+    assert!(registry.get("a").is_none());
+    assert_eq!(registry.list().iter().map(|r| r.task_id.as_str()).collect::<Vec<_>>(), ["b"]);
+}
+
+#[test]
+fn reschedule_moves_the_start_time_while_waiting() {
+    let task = TaskHandle::new(record("a", Some("2025-07-01 20:00:00")));
+    let start_time = task.start_time();
+    assert_eq!(*start_time.borrow(), Some(Local.with_ymd_and_hms(2025, 7, 1, 20, 0, 0).unwrap()));
+
+    task.reschedule("2025-07-01T21:30:00").unwrap();
+    assert!(start_time.has_changed().unwrap());
+    assert_eq!(*start_time.borrow(), Some(Local.with_ymd_and_hms(2025, 7, 1, 21, 30, 0).unwrap()));
+    assert_eq!(task.record().time_start.as_deref(), Some("2025-07-01T21:30:00"));
+
+    assert_eq!(task.reschedule("tonight").unwrap_err().kind(), ErrorKind::InvalidInput);
+
+    task.update(|r| r.phase = Phase::Preparing);
+    assert_eq!(task.reschedule("2025-07-01 22:00:00").unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(task.record().time_start.as_deref(), Some("2025-07-01T21:30:00"));
+}
+
+#[test]
+fn pause_holds_until_resumed() {
+    let task = TaskHandle::new(record("a", None));
+    let paused = task.paused();
+    task.pause().unwrap();
+    assert!(task.is_paused() && *paused.borrow());
+    assert!(task.record().paused);
+
+    task.resume().unwrap();
+    assert!(!task.is_paused());
+    assert!(!task.record().paused);
+
+    task.update(|r| r.phase = Phase::Done);
+    assert_eq!(task.pause().unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(task.resume().unwrap_err().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn exclusive_task_stops_its_group_when_it_orders() {
+    let exclusive = |task_id: &str, buyers: serde_json::Value| {
+        let mut info = ticket_info();
+        info.buyer_info = buyers;
+        let options = BuyOptions { exclusive: true, ..BuyOptions::from_settings(&Settings::default()) };
+        TaskHandle::new(TaskRecord::new(task_id.to_string(), &info, &options))
+    };
+    let a = exclusive("a", json!([{ "id": 1, "name": "张三" }, { "id": 2, "name": "李四" }]));
+    let b = exclusive("b", json!([{ "id": 2, "name": "李四" }, { "id": 1, "name": "张三" }]));
+    let c = exclusive("c", json!([{ "id": 1, "name": "张三" }]));
+    let d = exclusive("d", json!([{ "id": 1, "name": "张三" }, { "id": 2, "name": "李四" }]));
+    let plain = TaskHandle::new(record("plain", None));
+    assert_eq!(a.record().group.as_deref(), Some("10001:1,2"));
+    assert_eq!(plain.record().group, None);
+
+    let registry = TaskRegistry::default();
+    d.update(|r| r.phase = Phase::Failed);
+    for task in [&a, &b, &c, &d, &plain] {
+        registry.insert(task.clone());
+    }
+
+    assert_eq!(b.stop_siblings(), ["a"]);
+    assert!(a.is_stopped() && a.superseded_by().as_deref() == Some("b"));
+    assert!(!b.is_stopped() && b.superseded_by().is_none());
+    assert!(!c.is_stopped() && !d.is_stopped() && !plain.is_stopped());
+    assert!(b.stop_siblings().is_empty());
+
+    // Added after the others, e.g. restored from tasks.json
+    let e = exclusive("e", json!([{ "id": 1, "name": "张三" }]));
+    registry.insert(e.clone());
+    assert_eq!(c.stop_siblings(), ["e"]);
+}
+
+#[test]
+fn accounts_without_a_uid_are_grouped_by_their_cookies() {
+    let options = BuyOptions { exclusive: true, ..BuyOptions::from_settings(&Settings::default()) };
+    let group = |cookies: &[&str]| {
+        let mut info = ticket_info();
+        info.cookies = cookies.iter().map(|c| c.to_string()).collect();
+        TaskRecord::new("a".to_string(), &info, &options).group.unwrap()
+    };
+    let first = group(&["SESSDATA=first", "bili_jct=x"]);
+    assert_ne!(first, group(&["SESSDATA=second", "bili_jct=x"]));
+    assert_eq!(first, group(&["SESSDATA=first", "bili_jct=x"]));
+}
+
+fn stored(task_id: &str, time_start: &str) -> StoredTask {
+    StoredTask {
+        task_id: task_id.to_string(),
+        ticket_info: ticket_info(),
+        options: BuyOptions {
+            time_start: Some(time_start.to_string()),
+            ..BuyOptions::from_settings(&Settings::default())
+        },
+    }
+}
+
+#[test]
+fn saved_tasks_round_trip_and_are_removed() {
+    let dir = tempfile::tempdir().unwrap();
+    storage::save_task(dir.path(), stored("a", "2025-07-01 20:00:00")).unwrap();
+    storage::save_task(dir.path(), stored("b", "2025-07-02 20:00:00")).unwrap();
+    // Saving again replaces the entry
+    storage::save_task(dir.path(), stored("a", "2025-07-03 20:00:00")).unwrap();
+
+    let tasks = storage::get_tasks(dir.path()).unwrap();
+    assert_eq!(tasks.len(), 2);
+    let a = tasks.iter().find(|t| t.task_id == "a").unwrap();
+    assert_eq!(a.options.time_start.as_deref(), Some("2025-07-03 20:00:00"));
+    assert_eq!(a.ticket_info.cookies.len(), 2);
+
+    storage::remove_task(dir.path(), "a").unwrap();
+    storage::remove_task(dir.path(), "missing").unwrap();
+    assert_eq!(storage::get_tasks(dir.path()).unwrap().iter().map(|t| t.task_id.as_str()).collect::<Vec<_>>(), ["b"]);
+}
+
+#[test]
+fn flush_removes_interrupted_writes() {
+    let dir = tempfile::tempdir().unwrap();
+    storage::save_task(dir.path(), stored("a", "2025-07-01 20:00:00")).unwrap();
+    std::fs::write(dir.path().join("history.json.tmp"), "[{\"order_id\"").unwrap();
+
+    assert_eq!(storage::flush(dir.path()).unwrap(), 1);
+    assert!(!dir.path().join("history.json.tmp").exists());
+    assert_eq!(storage::get_tasks(dir.path()).unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn shutdown_stops_tasks_and_waits_for_orders() {
+    let registry = TaskRegistry::default();
+    let waiting = TaskHandle::new(record("waiting", Some("2025-07-01 20:00:00")));
+    let creating = TaskHandle::new(record("creating", None));
+    let paying = TaskHandle::new(record("paying", None));
+    paying.update(|r| r.phase = Phase::FetchingPayment);
+    for task in [&waiting, &creating, &paying] {
+        registry.insert(task.clone());
+    }
+
+    let guard = creating.begin_create();
+    let in_flight: Vec<_> = registry.orders_in_flight().into_iter().map(|r| r.task_id).collect();
+    assert_eq!(in_flight.len(), 2);
+    assert!(in_flight.contains(&"creating".to_string()) && in_flight.contains(&"paying".to_string()));
+    drop(guard);
+    assert!(!creating.order_in_flight());
+
+    registry.stop_all();
+    assert!(waiting.is_stopped() && creating.is_stopped() && paying.is_stopped());
+    waiting.update(|r| r.phase = Phase::Stopped);
+    creating.update(|r| r.phase = Phase::Stopped);
+    let unfinished = registry.wait_finished(std::time::Duration::from_millis(100)).await;
+    assert_eq!(unfinished.iter().map(|r| r.task_id.as_str()).collect::<Vec<_>>(), ["paying"]);
+
+    paying.update(|r| r.phase = Phase::Done);
+    assert!(registry.wait_finished(std::time::Duration::from_secs(1)).await.is_empty());
+}
+
+#[test]
+fn tasks_past_their_start_time_are_missed() {
+    let now = Local.with_ymd_and_hms(2025, 7, 1, 20, 0, 0).unwrap();
+    let tasks = vec![
+        stored("past", "2025-07-01 19:59:59"),
+        stored("exact", "2025-07-01 20:00:00"),
+        stored("future", "2025-07-01T20:00:01"),
+        stored("garbage", "tonight"),
+    ];
+
+    let (pending, missed) = split_missed(tasks, now);
+    assert_eq!(pending.iter().map(|t| t.task_id.as_str()).collect::<Vec<_>>(), ["future"]);
+    assert_eq!(missed.iter().map(|t| t.task_id.as_str()).collect::<Vec<_>>(), ["past", "exact", "garbage"]);
+
+    let record = TaskRecord::missed(&missed[0]);
+    assert_eq!(record.phase, Phase::Failed);
+    assert!(record.missed_start);
+    assert!(record.finished_at.is_some());
+}
@@ -0,0 +1,116 @@
+use bili_ticket_core::config::Endpoints;
+use bili_ticket_core::timesync::{self, Sample, TimeSource, TimeUnit};
+use serde_json::json;
+use std::net::UdpSocket;
+
+/// Samples of a server `offset_ms` ahead with a clock truncated to seconds,
+/// one every `gap_ms` with a round trip of `rtt_ms`.
+fn second_resolution(offset_ms: f64, rtt_ms: f64, gap_ms: f64, count: usize) -> Vec<Sample> {
+    (0..count)
+        .map(|i| {
+            let sent = 1_751_371_200_000.0 + i as f64 * gap_ms;
+            let server = ((sent + rtt_ms / 2.0 + offset_ms) / 1000.0).floor() * 1000.0;
+            Sample::http(sent, sent + rtt_ms, server, 1000.0)
+        })
+        .collect()
+}
+
+#[test]
+fn samples_across_second_boundaries_narrow_the_offset() {
+    let one = timesync::estimate(&second_resolution(250.0, 40.0, 170.0, 1)).unwrap();
+    assert_eq!(one.uncertainty_ms, 520.0);
+
+    let sync = timesync::estimate(&second_resolution(250.0, 40.0, 170.0, 8)).unwrap();
+    assert!((sync.offset_ms - 250.0).abs() <= sync.uncertainty_ms, "{:?}", sync);
+    assert!(sync.uncertainty_ms < 150.0, "{:?}", sync);
+    assert_eq!((sync.best_rtt_ms, sync.samples, sync.discarded), (40.0, 8, 0));
+}
+
+#[test]
+fn delayed_and_inconsistent_samples_are_discarded() {
+    let mut samples = second_resolution(250.0, 40.0, 170.0, 6);
+    // Stuck in a queue for half a second
+    samples.push(Sample::http(1_751_371_201_000.0, 1_751_371_201_500.0, 1_751_371_201_000.0, 1000.0));
+    // Answered by a server three seconds off
+    samples.push(Sample::http(1_751_371_202_000.0, 1_751_371_202_045.0, 1_751_371_205_000.0, 1000.0));
+
+    let sync = timesync::estimate(&samples).unwrap();
+    assert_eq!((sync.samples, sync.discarded), (6, 2));
+    assert!((sync.offset_ms - 250.0).abs() <= sync.uncertainty_ms, "{:?}", sync);
+    assert!(timesync::estimate(&[]).is_none());
+}
+
+#[test]
+fn ntp_sample_is_compensated_by_half_the_delay() {
+    // 20ms on the wire, 1ms in the server, server 1000ms ahead
+    let s = Sample::ntp(0.0, 1010.0, 1011.0, 21.0);
+    assert_eq!((s.offset_ms(), s.rtt_ms), (1000.0, 20.0));
+    assert_eq!((s.low_ms, s.high_ms), (990.0, 1010.0));
+}
+
+/// Answers like an NTP server whose clock is 5s ahead, on `bind`.
+fn ntp_responder(bind: &str) -> std::net::SocketAddr {
+    let server = UdpSocket::bind(bind).unwrap();
+    let address = server.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 48];
+        while let Ok((_, peer)) = server.recv_from(&mut buf) {
+            let now = timesync::local_ms() + 5000.0;
+            let secs = now / 1000.0 + 2_208_988_800.0;
+            let stamp = (((secs.trunc() as u64) << 32) | (secs.fract() * 4_294_967_296.0) as u64).to_be_bytes();
+            let mut reply = [0u8; 48];
+            reply[0] = 0x24;
+            reply[1] = 2;
+            reply[24..32].copy_from_slice(&buf[40..48]);
+            reply[32..40].copy_from_slice(&stamp);
+            reply[40..48].copy_from_slice(&stamp);
+            server.send_to(&reply, peer).unwrap();
+        }
+    });
+    address
+}
+
+#[tokio::test]
+async fn ntp_server_is_queried_over_udp() {
+    let address = ntp_responder("127.0.0.1:0").to_string();
+    let sync = timesync::measure(&reqwest::Client::new(), &TimeSource::from_url(&address), 3).await.unwrap();
+    assert!((sync.offset_ms - 5000.0).abs() < 50.0, "{:?}", sync);
+    assert_eq!(sync.samples, 3);
+}
+
+#[tokio::test]
+async fn ipv6_ntp_servers_are_reached() {
+    let address = ntp_responder("[::1]:0");
+    let sample = timesync::sample_ntp(&address.to_string()).await.unwrap();
+    assert!((sample.offset_ms() - 5000.0).abs() < 50.0, "{:?}", sample);
+    // Without a port, bracketed or not, it goes to 123 where nothing answers
+    for server in ["::1", "[::1]"] {
+        let err = timesync::sample_ntp(server).await.unwrap_err();
+        assert!(err.to_string().contains("[::1]:123"), "{}", err);
+    }
+}
+
+#[test]
+fn configured_sources_come_before_the_legacy_server_and_bilibili() {
+    let endpoints = Endpoints::default();
+    let configured: Vec<TimeSource> = serde_json::from_value(json!([
+        { "url": "https://api.m.taobao.com/rest/api3.do?api=mtop.common.getTimestamp", "pointer": "/data/t", "unit": "ms" },
+        { "url": "https://www.baidu.com", "date_header": true },
+        { "url": " " }
+    ]))
+    .unwrap();
+    assert_eq!(configured[1].unit, TimeUnit::Millis);
+    // A pointer without a unit could be either
+    let err = serde_json::from_value::<TimeSource>(json!({ "url": "https://example.com/time", "pointer": "/now" })).unwrap_err();
+    assert!(err.to_string().contains("needs a unit"), "{}", err);
+
+    let urls = |chain: Vec<TimeSource>| chain.into_iter().map(|s| s.url).collect::<Vec<_>>();
+    assert_eq!(
+        urls(timesync::sources(&configured, Some("ntp.aliyun.com"), &endpoints)),
+        [configured[0].url.as_str(), "https://www.baidu.com", "ntp.aliyun.com", endpoints.click_now().as_str()]
+    );
+    // Bilibili listed explicitly is not tried twice
+    let chain = timesync::sources(&[TimeSource::bilibili(&endpoints)], None, &endpoints);
+    assert_eq!(chain, [TimeSource::bilibili(&endpoints)]);
+    assert_eq!(serde_json::to_value(&chain[0]).unwrap(), json!({ "url": endpoints.click_now(), "pointer": "/data/now", "unit": "s" }));
+}
@@ -0,0 +1,421 @@
+//! Mock Bilibili ticket server for offline end-to-end testing.
+//!
+//! Serves the project, buyer, address, order, server time and QR login
+//! endpoints the core crate talks to. Responses of `order/prepare`,
+//! `order/createV2`, `order/getPayParam` and the QR poll endpoint follow
+//! scriptable errno sequences, so retry paths of `start_buy_task` can be
+//! exercised without network access:
+//!
+//! ```no_run
+//! use bili_ticket_mock::{ErrnoScript, MockServer};
+//!
+//! let server = MockServer::start().unwrap();
+//! server.script_create(ErrnoScript::new().then(900001, 3).then(100051, 1).then(0, 1));
+//! let endpoints = server.endpoints();
+//! ```
+
+use bili_ticket_core::config::Endpoints;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tiny_http::{Header, Request, Response, Server};
+
+/// Ordered errno replies for one endpoint. Once the queue is used up every
+/// further request gets the fallback errno.
+#[derive(Debug, Clone, Default)]
+pub struct ErrnoScript {
+    queue: VecDeque<i64>,
+    fallback: i64,
+}
+
+impl ErrnoScript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reply with `errno` to the next `times` requests.
+    pub fn then(mut self, errno: i64, times: usize) -> Self {
+        self.queue.extend(std::iter::repeat_n(errno, times));
+        self
+    }
+
+    /// Errno used after the scripted replies run out (0 by default).
+    pub fn otherwise(mut self, errno: i64) -> Self {
+        self.fallback = errno;
+        self
+    }
+
+    fn next(&mut self) -> i64 {
+        self.queue.pop_front().unwrap_or(self.fallback)
+    }
+}
+
+/// A request received by the mock server.
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path without query string
+    pub path: String,
+    pub query: String,
+    pub body: String,
+    pub cookie: Option<String>,
+}
+
+struct MockState {
+    project: Value,
+    linkgoods: Vec<Value>,
+    buyers: Vec<Value>,
+    addresses: Vec<Value>,
+    prepare: ErrnoScript,
+    create: ErrnoScript,
+    pay_param: ErrnoScript,
+    qrcode_poll: ErrnoScript,
+    /// `pay_money` returned with errno 100034
+    repriced_money: u64,
+    next_order_id: u64,
+    requests: Vec<RecordedRequest>,
+}
+
+/// Mock server bound to an ephemeral port on 127.0.0.1. Stops on drop.
+pub struct MockServer {
+    server: Arc<Server>,
+    state: Arc<Mutex<MockState>>,
+    url: String,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    pub fn start() -> std::io::Result<Self> {
+        Self::bind("127.0.0.1:0")
+    }
+
+    pub fn bind(addr: &str) -> std::io::Result<Self> {
+        let server = Server::http(addr).map_err(std::io::Error::other)?;
+        let port = server.server_addr().to_ip().map(|a| a.port()).unwrap_or_default();
+        let server = Arc::new(server);
+        let state = Arc::new(Mutex::new(MockState {
+            project: default_project(),
+            linkgoods: vec![default_linkgoods()],
+            buyers: default_buyers(),
+            addresses: default_addresses(),
+            prepare: ErrnoScript::new(),
+            create: ErrnoScript::new(),
+            pay_param: ErrnoScript::new(),
+            qrcode_poll: ErrnoScript::new(),
+            repriced_money: 48000,
+            next_order_id: 1000000001,
+            requests: Vec::new(),
+        }));
+
+        let server_clone = server.clone();
+        let state_clone = state.clone();
+        let handle = std::thread::spawn(move || {
+            for request in server_clone.incoming_requests() {
+                handle_request(&state_clone, request);
+            }
+        });
+
+        Ok(Self {
+            server,
+            state,
+            url: format!("http://127.0.0.1:{}", port),
+            handle: Some(handle),
+        })
+    }
+
+    /// Base URL, e.g. `http://127.0.0.1:53124`.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Endpoints with every host pointed at this server.
+    pub fn endpoints(&self) -> Endpoints {
+        Endpoints::all(&self.url)
+    }
+
+    pub fn script_prepare(&self, script: ErrnoScript) {
+        self.state.lock().unwrap().prepare = script;
+    }
+
+    pub fn script_create(&self, script: ErrnoScript) {
+        self.state.lock().unwrap().create = script;
+    }
+
+    pub fn script_pay_param(&self, script: ErrnoScript) {
+        self.state.lock().unwrap().pay_param = script;
+    }
+
+    /// Script the `data.code` of the QR poll endpoint (86101 = not scanned,
+    /// 86090 = scanned, 0 = confirmed).
+    pub fn script_qrcode_poll(&self, script: ErrnoScript) {
+        self.state.lock().unwrap().qrcode_poll = script;
+    }
+
+    /// Replace the `data` object returned by `project/getV2`.
+    pub fn set_project(&self, project: Value) {
+        self.state.lock().unwrap().project = project;
+    }
+
+    /// Change the stock state of a SKU of the current project, e.g. to
+    /// simulate returned tickets.
+    pub fn set_sku_sale_flag(&self, sku_id: i64, clickable: bool, sale_flag: i64) {
+        self.update_sku(sku_id, |sku| {
+            sku["clickable"] = json!(clickable);
+            sku["sale_flag"]["number"] = json!(sale_flag);
+        });
+    }
+
+    /// Publish the sale start of a SKU as unix seconds, e.g. to simulate
+    /// Bilibili moving the sale.
+    pub fn set_sku_sale_start(&self, sku_id: i64, sale_start: i64) {
+        self.update_sku(sku_id, |sku| sku["saleStart"] = json!(sale_start));
+    }
+
+    fn update_sku(&self, sku_id: i64, f: impl Fn(&mut Value)) {
+        let mut state = self.state.lock().unwrap();
+        let screens = state.project["screen_list"].as_array_mut().into_iter().flatten();
+        for sku in screens.flat_map(|s| s["ticket_list"].as_array_mut().into_iter().flatten()) {
+            if sku["id"] == sku_id {
+                f(sku);
+            }
+        }
+    }
+
+    /// Replace the linked goods returned by `linkgoods/list` and `detail`.
+    pub fn set_linkgoods(&self, linkgoods: Vec<Value>) {
+        self.state.lock().unwrap().linkgoods = linkgoods;
+    }
+
+    pub fn set_buyers(&self, buyers: Vec<Value>) {
+        self.state.lock().unwrap().buyers = buyers;
+    }
+
+    pub fn set_addresses(&self, addresses: Vec<Value>) {
+        self.state.lock().unwrap().addresses = addresses;
+    }
+
+    /// Price returned in `data.pay_money` when `createV2` replies 100034.
+    pub fn set_repriced_money(&self, pay_money: u64) {
+        self.state.lock().unwrap().repriced_money = pay_money;
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Number of requests received for `path`, e.g. `/api/ticket/order/createV2`.
+    pub fn count(&self, path: &str) -> usize {
+        self.state.lock().unwrap().requests.iter().filter(|r| r.path == path).count()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+fn show_reply(errno: i64, data: Value) -> Value {
+    json!({
+        "errno": errno,
+        "code": errno,
+        "msg": if errno == 0 { "" } else { "mock error" },
+        "data": data,
+    })
+}
+
+fn api_reply(code: i64, data: Value) -> Value {
+    json!({
+        "code": code,
+        "message": if code == 0 { "0" } else { "mock error" },
+        "data": data,
+    })
+}
+
+fn query_param<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query.split('&').find_map(|pair| {
+        let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+        (k == key).then_some(v)
+    })
+}
+
+fn handle_request(state: &Mutex<MockState>, mut request: Request) {
+    let (path, query) = match request.url().split_once('?') {
+        Some((p, q)) => (p.to_string(), q.to_string()),
+        None => (request.url().to_string(), String::new()),
+    };
+    let mut body = String::new();
+    let _ = request.as_reader().read_to_string(&mut body);
+    let cookie = request.headers().iter()
+        .find(|h| h.field.equiv("Cookie"))
+        .map(|h| h.value.to_string());
+
+    let mut state = state.lock().unwrap();
+    state.requests.push(RecordedRequest {
+        method: request.method().to_string(),
+        path: path.clone(),
+        query: query.clone(),
+        body,
+        cookie: cookie.clone(),
+    });
+
+    let mut set_cookies = Vec::new();
+    let reply = match path.as_str() {
+        "/api/ticket/project/getV2" => show_reply(0, state.project.clone()),
+        "/api/ticket/linkgoods/list" => {
+            let list: Vec<Value> = state.linkgoods.iter()
+                .map(|g| json!({ "id": g["id"], "name": g["name"] }))
+                .collect();
+            show_reply(0, json!({ "list": list }))
+        }
+        "/api/ticket/linkgoods/detail" => {
+            let link_id = query_param(&query, "link_id").unwrap_or_default();
+            match state.linkgoods.iter().find(|g| g["id"].to_string().trim_matches('"') == link_id) {
+                Some(goods) => show_reply(0, goods.clone()),
+                None => show_reply(-404, Value::Null),
+            }
+        }
+        "/api/ticket/buyer/list" => show_reply(0, json!({ "list": state.buyers })),
+        "/api/ticket/addr/list" => show_reply(0, json!({ "addr_list": state.addresses })),
+        "/api/ticket/order/prepare" => {
+            let errno = state.prepare.next();
+            let data = if errno == 0 {
+                json!({ "token": format!("mock-token-{}", state.requests.len()), "ptoken": "mock-ptoken" })
+            } else {
+                json!({})
+            };
+            show_reply(errno, data)
+        }
+        "/api/ticket/order/createV2" => {
+            let errno = state.create.next();
+            let data = match errno {
+                0 => {
+                    let order_id = state.next_order_id;
+                    state.next_order_id += 1;
+                    json!({ "orderId": order_id, "orderCreateTime": now_secs() })
+                }
+                100034 => json!({ "pay_money": state.repriced_money }),
+                _ => json!({}),
+            };
+            show_reply(errno, data)
+        }
+        "/api/ticket/order/getPayParam" => {
+            let errno = state.pay_param.next();
+            let order_id = query_param(&query, "order_id").unwrap_or_default();
+            let data = if errno == 0 {
+                json!({ "code_url": format!("https://mock.pay/qr?order_id={}", order_id) })
+            } else {
+                json!({})
+            };
+            show_reply(errno, data)
+        }
+        "/x/report/click/now" => api_reply(0, json!({ "now": now_secs() })),
+        "/x/web-interface/nav" => {
+            if cookie.as_deref().unwrap_or_default().contains("SESSDATA") {
+                api_reply(0, json!({
+                    "isLogin": true,
+                    "mid": 10001,
+                    "uname": "mock_user",
+                    "face": "https://mock.face/10001.jpg",
+                    "level_info": { "current_level": 6 },
+                    "vipStatus": 1,
+                    "money": 12.5,
+                }))
+            } else {
+                api_reply(-101, json!({ "isLogin": false }))
+            }
+        }
+        "/x/passport-login/web/qrcode/generate" => api_reply(0, json!({
+            "url": "https://mock.passport/qr?qrcode_key=mock-qrcode-key",
+            "qrcode_key": "mock-qrcode-key",
+        })),
+        "/x/passport-login/web/qrcode/poll" => {
+            let code = state.qrcode_poll.next();
+            if code == 0 {
+                set_cookies = vec![
+                    "SESSDATA=mock-sessdata; Path=/; HttpOnly",
+                    "bili_jct=mock-csrf; Path=/",
+                    "DedeUserID=10001; Path=/",
+                ];
+            }
+            api_reply(0, json!({ "code": code, "message": "", "url": "" }))
+        }
+        _ => api_reply(-404, Value::Null),
+    };
+    drop(state);
+
+    let mut response = Response::from_string(reply.to_string())
+        .with_header(Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap());
+    for c in set_cookies {
+        response.add_header(Header::from_bytes(&b"Set-Cookie"[..], c.as_bytes()).unwrap());
+    }
+    let _ = request.respond(response);
+}
+
+fn default_project() -> Value {
+    json!({
+        "id": 85939,
+        "name": "Mock Festival 2025",
+        "has_eticket": false,
+        "sale_flag": "预售中",
+        "screen_list": [
+            {
+                "id": 1001,
+                "name": "2025-07-11 周五",
+                "express_fee": 1000,
+                "sale_flag": { "number": 2, "display_name": "预售中" },
+                "ticket_list": [
+                    { "id": 2001, "desc": "普通票", "price": 38000, "screen_name": "2025-07-11 周五", "clickable": true, "sale_flag": { "number": 2, "display_name": "预售中" } },
+                    { "id": 2002, "desc": "VIP票", "price": 88000, "screen_name": "2025-07-11 周五", "clickable": false, "sale_flag": { "number": 4, "display_name": "已售罄" } }
+                ]
+            }
+        ]
+    })
+}
+
+fn default_linkgoods() -> Value {
+    json!({
+        "id": 501,
+        "name": "场贩套装",
+        "item_id": 85940,
+        "specs_list": [
+            {
+                "id": 3001,
+                "name": "场贩 A",
+                "express_fee": 0,
+                "ticket_list": [
+                    { "id": 4001, "desc": "套装 A", "price": 9900, "clickable": true }
+                ]
+            }
+        ]
+    })
+}
+
+fn default_buyers() -> Vec<Value> {
+    vec![
+        json!({ "id": 1, "name": "张三", "personal_id": "1101**********1234", "id_type": 0, "tel": "138****0000" }),
+        json!({ "id": 2, "name": "李四", "personal_id": "3101**********5678", "id_type": 0, "tel": "139****1111" }),
+    ]
+}
+
+fn default_addresses() -> Vec<Value> {
+    vec![json!({
+        "id": 9001,
+        "name": "张三",
+        "phone": "13800000000",
+        "prov": "上海市",
+        "city": "上海市",
+        "area": "浦东新区",
+        "addr": "世纪大道 1 号",
+        "def": 1,
+    })]
+}
@@ -0,0 +1,13 @@
+use bili_ticket_mock::MockServer;
+
+/// Run the mock server in the foreground, e.g. to point the GUI's
+/// `config.json` endpoints at it: `bili-ticket-mock [addr]`.
+fn main() -> std::io::Result<()> {
+    let addr = std::env::args().nth(1).unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let server = MockServer::bind(&addr)?;
+    println!("Mock Bilibili server listening on {}", addr);
+    println!("{}", serde_json::json!({ "endpoints": server.endpoints() }));
+    loop {
+        std::thread::park();
+    }
+}
@@ -0,0 +1,795 @@
+use bili_ticket_core::error::Result;
+use bili_ticket_core::buy::{self, BuyOptions, TicketInfo};
+use bili_ticket_core::clock::Clock;
+use bili_ticket_core::config::{Endpoints, Settings};
+use bili_ticket_core::event::{EventSink, TaskEvent};
+use bili_ticket_core::http::ClientPool;
+use bili_ticket_core::phase::Phase;
+use bili_ticket_core::task::{TaskHandle, TaskRecord, TaskRegistry};
+use bili_ticket_core::{api, auth, storage, timesync};
+use bili_ticket_core::timesync::{TimeSource, TimeUnit};
+use bili_ticket_mock::{ErrnoScript, MockServer};
+use chrono::{Local, TimeZone};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+#[derive(Default)]
+struct CollectSink(Mutex<Vec<(String, Value)>>);
+
+impl EventSink for CollectSink {
+    fn emit(&self, event: &str, payload: Value) -> Result<()> {
+        self.0.lock().unwrap().push((event.to_string(), payload));
+        Ok(())
+    }
+}
+
+impl CollectSink {
+    fn events(&self, name: &str) -> Vec<Value> {
+        self.0.lock().unwrap().iter().filter(|(e, _)| e == name).map(|(_, p)| p.clone()).collect()
+    }
+}
+
+fn ticket_info() -> TicketInfo {
+    serde_json::from_value(json!({
+        "project_id": "85939",
+        "project_name": "Mock Festival 2025",
+        "screen_id": "1001",
+        "sku_id": "2001",
+        "count": 1,
+        "buyer_info": [{ "id": 1, "name": "张三" }],
+        "deliver_info": {},
+        "cookies": ["SESSDATA=mock-sessdata", "bili_jct=mock-csrf"],
+        "pay_money": 39000,
+        "contact_name": "张三",
+        "contact_tel": "13800000000"
+    }))
+    .unwrap()
+}
+
+/// `time_start` this far from now, in the format tasks take.
+fn from_now(delta: chrono::Duration) -> String {
+    (Local::now() + delta).format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// One buy task against a [`MockServer`]: retries every 10ms with no time
+/// offset, a clock that is never synced, [`ticket_info`] and the default
+/// settings otherwise.
+struct Run {
+    sink: Arc<dyn EventSink>,
+    pool: ClientPool,
+    endpoints: Endpoints,
+    dir: PathBuf,
+    info: TicketInfo,
+    options: BuyOptions,
+    clock: Option<Clock>,
+    id: String,
+    task: Option<Arc<TaskHandle>>,
+}
+
+impl Run {
+    fn new(server: &MockServer, sink: Arc<dyn EventSink>, dir: &Path) -> Self {
+        Self {
+            sink,
+            pool: ClientPool::new(server.endpoints()).unwrap(),
+            endpoints: server.endpoints(),
+            dir: dir.to_path_buf(),
+            info: ticket_info(),
+            options: BuyOptions {
+                interval: 10,
+                time_offset: Some(0.0),
+                ..BuyOptions::from_settings(&Settings::default())
+            },
+            clock: None,
+            id: "task-1".to_string(),
+            task: None,
+        }
+    }
+
+    fn info(mut self, info: TicketInfo) -> Self {
+        self.info = info;
+        self
+    }
+
+    fn options(mut self, change: impl FnOnce(&mut BuyOptions)) -> Self {
+        change(&mut self.options);
+        self
+    }
+
+    fn clock(mut self, clock: Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    fn id(mut self, id: &str) -> Self {
+        self.id = id.to_string();
+        self
+    }
+
+    /// The handle the task will run as, made from the info and options so far.
+    fn task(&mut self) -> Arc<TaskHandle> {
+        let (id, info, options) = (&self.id, &self.info, &self.options);
+        self.task.get_or_insert_with(|| TaskHandle::new(TaskRecord::new(id.clone(), info, options))).clone()
+    }
+
+    fn spawn(mut self) -> (Arc<TaskHandle>, JoinHandle<Result<()>>) {
+        let task = self.task();
+        let run = buy::start_buy_task(
+            self.sink,
+            self.pool.for_cookies(&self.info.cookies).unwrap(),
+            self.endpoints,
+            self.clock.unwrap_or_else(|| Clock::new(self.pool.anonymous(), Vec::new())),
+            task.clone(),
+            self.info,
+            self.options,
+            self.dir,
+        );
+        (task, tokio::spawn(run))
+    }
+
+    /// Run to the end, within 5s.
+    async fn run(self) -> Arc<TaskHandle> {
+        let (task, run) = self.spawn();
+        tokio::time::timeout(Duration::from_secs(5), run).await.unwrap().unwrap().unwrap();
+        task
+    }
+}
+
+#[tokio::test]
+async fn retries_through_congestion_and_expired_token_then_succeeds() {
+    let server = MockServer::start().unwrap();
+    server.script_create(ErrnoScript::new().then(900001, 3).then(100051, 1).then(0, 1));
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+
+    let record = Run::new(&server, sink.clone(), dir.path()).run().await.record();
+
+    // 100051 forces a second prepare before the successful create
+    assert_eq!(server.count("/api/ticket/order/prepare"), 2);
+    assert_eq!(server.count("/api/ticket/order/createV2"), 5);
+    assert_eq!(server.count("/api/ticket/order/getPayParam"), 1);
+
+    let results = sink.events("task_finished");
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["success"], true);
+    assert_eq!(results[0]["reason"], "order_created");
+
+    let attempts: Vec<_> = sink.events("attempt_result").into_iter()
+        .map(|p| serde_json::from_value::<TaskEvent>(p).unwrap())
+        .collect();
+    let errnos: Vec<_> = attempts.iter().map(|e| match e {
+        TaskEvent::AttemptResult { errno, .. } => errno.unwrap(),
+        other => panic!("unexpected {:?}", other),
+    }).collect();
+    assert_eq!(errnos, [900001, 900001, 900001, 100051, 0]);
+    assert!(matches!(attempts[4], TaskEvent::AttemptResult { attempt: 5, round_attempt: 1, .. }));
+    assert_eq!(sink.events("prepare_result").len(), 2);
+    assert_eq!(
+        serde_json::from_value::<TaskEvent>(sink.events("order_created")[0].clone()).unwrap(),
+        TaskEvent::OrderCreated { order_id: Some("1000000001".to_string()), price: Some(39000) }
+    );
+
+    let phases: Vec<_> = sink.events("task_phase").iter().map(|p| p["phase"].as_str().unwrap().to_string()).collect();
+    assert_eq!(phases, ["preparing", "creating", "preparing", "creating", "fetching_payment", "done"]);
+
+    assert_eq!(record.phase, Phase::Done);
+    assert_eq!((record.prepares, record.attempts, record.last_errno), (2, 5, Some(0)));
+    assert_eq!(record.buyers, ["张三"]);
+    assert!(record.finished_at.is_some());
+
+    let payments = sink.events("payment_ready");
+    assert_eq!(payments.len(), 1);
+    assert!(payments[0]["url"].as_str().unwrap().contains("1000000001"));
+
+    let history = storage::get_history(dir.path()).unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].order_id, "1000000001");
+    assert_eq!(history[0].price, 39000);
+}
+
+#[tokio::test]
+async fn finite_mode_stops_after_total_attempts() {
+    let server = MockServer::start().unwrap();
+    server.script_create(ErrnoScript::new().otherwise(900001));
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+
+    Run::new(&server, sink.clone(), dir.path()).options(|o| (o.mode, o.total_attempts) = (1, 2)).run().await;
+
+    assert_eq!(server.count("/api/ticket/order/prepare"), 2);
+    assert_eq!(server.count("/api/ticket/order/createV2"), 4);
+    let results = sink.events("task_finished");
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["success"], false);
+    assert_eq!(results[0]["reason"], "attempts_exhausted");
+    assert_eq!(sink.events("task_phase").last().unwrap()["phase"], "failed");
+    assert!(storage::get_history(dir.path()).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn failing_prepares_give_up_after_the_budget() {
+    let server = MockServer::start().unwrap();
+    server.script_prepare(ErrnoScript::new().otherwise(100001));
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+
+    let record = Run::new(&server, sink.clone(), dir.path()).options(|o| o.max_prepare_failures = 3).run().await.record();
+
+    assert_eq!(server.count("/api/ticket/order/prepare"), 3);
+    assert_eq!(server.count("/api/ticket/order/createV2"), 0);
+    assert_eq!(record.phase, Phase::Failed);
+    let results = sink.events("task_finished");
+    assert_eq!((results[0]["reason"].as_str(), results[0]["success"].as_bool()), (Some("prepare_failures"), Some(false)));
+}
+
+#[tokio::test]
+async fn failing_prepares_use_up_the_attempts_in_finite_mode() {
+    let server = MockServer::start().unwrap();
+    server.script_prepare(ErrnoScript::new().otherwise(100001));
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+
+    // No prepare failure budget by default
+    let settings = Settings::default();
+    assert_eq!(settings.buy.max_prepare_failures, 0);
+    let record = Run::new(&server, sink.clone(), dir.path())
+        .options(|o| (o.mode, o.total_attempts) = (1, settings.buy.total_attempts))
+        .run()
+        .await
+        .record();
+
+    assert_eq!(server.count("/api/ticket/order/prepare"), settings.buy.total_attempts as usize);
+    assert_eq!(record.phase, Phase::Failed);
+    assert_eq!(sink.events("task_finished")[0]["reason"], "attempts_exhausted");
+}
+
+#[tokio::test]
+async fn terminal_errno_ends_the_task() {
+    let server = MockServer::start().unwrap();
+    server.script_create(ErrnoScript::new().then(900001, 2).then(100039, 1));
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+
+    let record = Run::new(&server, sink.clone(), dir.path()).run().await.record();
+
+    assert_eq!(server.count("/api/ticket/order/createV2"), 3);
+    assert_eq!((record.phase, record.last_errno), (Phase::Failed, Some(100039)));
+    let results = sink.events("task_finished");
+    assert_eq!(results[0]["reason"], "terminal_errno");
+    assert!(results[0]["message"].as_str().unwrap().contains("100039"));
+}
+
+#[tokio::test]
+async fn request_budget_and_end_time_end_the_task() {
+    let server = MockServer::start().unwrap();
+    server.script_create(ErrnoScript::new().otherwise(900001));
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+
+    let record = Run::new(&server, sink.clone(), dir.path()).options(|o| o.max_requests = 5).run().await.record();
+    assert_eq!((record.prepares, record.attempts), (1, 4));
+    assert_eq!(sink.events("task_finished")[0]["reason"], "request_limit");
+
+    let sink = Arc::new(CollectSink::default());
+    let record = Run::new(&server, sink.clone(), dir.path())
+        .options(|o| o.time_end = Some("2000-01-01 00:00:00".to_string()))
+        .run()
+        .await
+        .record();
+    assert_eq!((record.prepares, record.phase), (0, Phase::Failed));
+    assert_eq!(sink.events("task_finished")[0]["reason"], "deadline_reached");
+}
+
+#[tokio::test]
+async fn end_time_before_the_start_time_ends_the_wait() {
+    let server = MockServer::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+
+    let task = Run::new(&server, sink.clone(), dir.path())
+        .options(|o| {
+            o.time_start = Some(from_now(chrono::Duration::hours(1)));
+            o.time_end = Some(from_now(chrono::Duration::seconds(1)));
+        })
+        .run()
+        .await;
+
+    assert_eq!(server.count("/api/ticket/order/prepare"), 0);
+    assert_eq!(task.record().phase, Phase::Failed);
+    assert_eq!(sink.events("task_finished")[0]["reason"], "deadline_reached");
+}
+
+#[tokio::test]
+async fn price_change_is_applied_to_following_attempts() {
+    let server = MockServer::start().unwrap();
+    server.set_repriced_money(48000);
+    server.script_create(ErrnoScript::new().then(100034, 1).then(0, 1));
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+
+    Run::new(&server, sink.clone(), dir.path()).run().await;
+
+    let creates: Vec<_> = server.requests().into_iter().filter(|r| r.path == "/api/ticket/order/createV2").collect();
+    assert_eq!(creates.len(), 2);
+    let second: Value = serde_json::from_str(&creates[1].body).unwrap();
+    assert_eq!(second["pay_money"], 48000);
+    assert_eq!(storage::get_history(dir.path()).unwrap()[0].price, 48000);
+}
+
+#[tokio::test]
+async fn sold_out_sku_falls_back_to_the_next_candidate() {
+    let server = MockServer::start().unwrap();
+    server.script_create(ErrnoScript::new().then(100009, 1).then(100017, 1).then(0, 1));
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+    let mut info = ticket_info();
+    info.count = 2;
+    info.fallbacks = serde_json::from_value(json!([
+        { "screen_id": "1001", "sku_id": "2002", "sku_name": "VIP", "price": 68000 },
+        { "screen_id": "1002", "screen_name": "Day 2", "sku_id": "2003", "price": 38000 }
+    ]))
+    .unwrap();
+    let task = Run::new(&server, sink.clone(), dir.path())
+        .info(info)
+        .options(|o| (o.mode, o.total_attempts) = (1, 1))
+        .run()
+        .await;
+
+    // Each candidate is prepared on its own; switching does not use up the single round
+    let bodies: Vec<Value> = server.requests().into_iter()
+        .filter(|r| r.path == "/api/ticket/order/createV2")
+        .map(|r| serde_json::from_str(&r.body).unwrap())
+        .collect();
+    assert_eq!(server.count("/api/ticket/order/prepare"), 3);
+    let skus: Vec<_> = bodies.iter().map(|b| (b["screen_id"].clone(), b["sku_id"].clone(), b["pay_money"].clone())).collect();
+    assert_eq!(skus, [
+        (json!("1001"), json!("2001"), json!(39000)),
+        (json!("1001"), json!("2002"), json!(136000)),
+        (json!("1002"), json!("2003"), json!(76000)),
+    ]);
+
+    let switched: Vec<_> = sink.events("sku_switched").iter().map(|p| (p["errno"].as_i64().unwrap(), p["sku_id"].as_str().unwrap().to_string())).collect();
+    assert_eq!(switched, [(100009, "2002".to_string()), (100017, "2003".to_string())]);
+    let record = task.record();
+    assert_eq!(record.phase, Phase::Done);
+    assert_eq!((record.sku_id.as_str(), record.screen_name.as_deref()), ("2003", Some("Day 2")));
+    assert_eq!(storage::get_history(dir.path()).unwrap()[0].price, 76000);
+}
+
+#[tokio::test]
+async fn sku_sold_out_at_prepare_falls_back_within_the_single_round() {
+    let server = MockServer::start().unwrap();
+    server.script_prepare(ErrnoScript::new().then(100009, 1).then(0, 1));
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+    let mut info = ticket_info();
+    info.fallbacks = serde_json::from_value(json!([
+        { "screen_id": "1001", "sku_id": "2002", "sku_name": "VIP", "price": 68000 }
+    ]))
+    .unwrap();
+    let task = Run::new(&server, sink.clone(), dir.path())
+        .info(info)
+        .options(|o| (o.mode, o.total_attempts) = (1, 1))
+        .run()
+        .await;
+
+    assert_eq!(server.count("/api/ticket/order/prepare"), 2);
+    assert_eq!(server.count("/api/ticket/order/createV2"), 1);
+    assert_eq!(sink.events("sku_switched")[0]["sku_id"], "2002");
+    let record = task.record();
+    assert_eq!(record.phase, Phase::Done);
+    assert_eq!(record.sku_id, "2002");
+}
+
+#[tokio::test]
+async fn watch_mode_waits_for_restock_before_preparing() {
+    let server = MockServer::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+    // 2002 is sold out in the mock project
+    let mut info = ticket_info();
+    info.sku_id = "2002".to_string();
+    let mut run = Run::new(&server, sink.clone(), dir.path()).info(info).options(|o| (o.watch, o.watch_interval) = (true, 20));
+    assert_eq!(run.task().record().phase, Phase::Watching);
+    let (task, run) = run.spawn();
+
+    while server.count("/api/ticket/project/getV2") < 3 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert_eq!(server.count("/api/ticket/order/prepare"), 0);
+    assert_eq!(task.record().phase, Phase::Watching);
+    server.set_sku_sale_flag(2002, true, 2);
+
+    tokio::time::timeout(Duration::from_secs(5), run).await.unwrap().unwrap().unwrap();
+    let restocked = sink.events("restocked");
+    assert_eq!(restocked.len(), 1);
+    assert_eq!((restocked[0]["sku_id"].as_str(), restocked[0]["sale_flag"].as_i64()), (Some("2002"), Some(2)));
+    assert_eq!(server.count("/api/ticket/order/prepare"), 1);
+    assert_eq!(task.record().phase, Phase::Done);
+}
+
+#[tokio::test]
+async fn watch_mode_goes_back_to_watching_when_sold_out_again() {
+    let server = MockServer::start().unwrap();
+    server.script_create(ErrnoScript::new().then(900001, 1).then(100009, 1).then(0, 1));
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+    Run::new(&server, sink.clone(), dir.path()).options(|o| (o.watch, o.watch_interval) = (true, 20)).run().await;
+
+    let phases: Vec<_> = sink.events("task_phase").iter().map(|p| p["phase"].as_str().unwrap().to_string()).collect();
+    assert_eq!(phases, ["watching", "preparing", "creating", "watching", "preparing", "creating", "fetching_payment", "done"]);
+    assert_eq!(sink.events("restocked").len(), 2);
+}
+
+#[tokio::test]
+async fn rescheduled_task_starts_at_the_new_time() {
+    let server = MockServer::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+    let (task, run) = Run::new(&server, sink.clone(), dir.path())
+        .options(|o| o.time_start = Some(from_now(chrono::Duration::hours(1))))
+        .spawn();
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(server.count("/api/ticket/order/prepare"), 0);
+    let now = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    task.reschedule(&now).unwrap();
+
+    tokio::time::timeout(Duration::from_secs(5), run).await.unwrap().unwrap().unwrap();
+    assert_eq!(sink.events("task_scheduled").len(), 1);
+    assert!(!sink.events("countdown").is_empty());
+    let rescheduled = sink.events("task_rescheduled");
+    assert_eq!(rescheduled.len(), 1);
+    assert_eq!(rescheduled[0]["time_start"], now.as_str());
+    assert_eq!(task.record().time_start.as_deref(), Some(now.as_str()));
+    assert_eq!(task.record().phase, Phase::Done);
+    // Only a waiting task can be moved
+    assert!(task.reschedule(&now).is_err());
+}
+
+#[tokio::test]
+async fn paused_task_resumes_with_the_same_token() {
+    let server = MockServer::start().unwrap();
+    server.script_create(ErrnoScript::new().then(900001, 5).then(0, 1));
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+    let (task, run) = Run::new(&server, sink.clone(), dir.path()).options(|o| o.interval = 50).spawn();
+
+    while server.count("/api/ticket/order/createV2") < 2 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    task.pause().unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let held_at = server.count("/api/ticket/order/createV2");
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(server.count("/api/ticket/order/createV2"), held_at);
+    assert_eq!(task.record().phase, Phase::Creating);
+
+    task.resume().unwrap();
+    tokio::time::timeout(Duration::from_secs(5), run).await.unwrap().unwrap().unwrap();
+    // Continued creating with the token from before the pause
+    assert_eq!(server.count("/api/ticket/order/prepare"), 1);
+    assert_eq!(server.count("/api/ticket/order/createV2"), 6);
+    assert_eq!(task.record().phase, Phase::Done);
+    let paused: Vec<_> = sink.events("task_paused").iter().map(|p| p["paused"].as_bool().unwrap()).collect();
+    assert_eq!(paused, [true, false]);
+}
+
+#[tokio::test]
+async fn paused_scheduled_task_waits_past_its_start_time() {
+    let server = MockServer::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+    let (task, run) = Run::new(&server, sink.clone(), dir.path())
+        .options(|o| o.time_start = Some(from_now(chrono::Duration::seconds(1))))
+        .spawn();
+
+    task.pause().unwrap();
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(server.count("/api/ticket/order/prepare"), 0);
+    assert_eq!(task.record().phase, Phase::Scheduled);
+
+    task.resume().unwrap();
+    tokio::time::timeout(Duration::from_secs(5), run).await.unwrap().unwrap().unwrap();
+    assert_eq!(task.record().phase, Phase::Done);
+    let paused: Vec<_> = sink.events("task_paused").iter().map(|p| (p["paused"].as_bool().unwrap(), p["phase"].clone())).collect();
+    assert_eq!(paused, [(true, json!("scheduled")), (false, json!("scheduled"))]);
+    assert!(task.resume().is_err());
+}
+
+#[tokio::test]
+async fn exclusive_order_stops_the_other_task_for_the_same_buyers() {
+    let server = MockServer::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+    let registry = TaskRegistry::default();
+    let mut waiting = Run::new(&server, sink.clone(), dir.path())
+        .id("waiting")
+        .options(|o| (o.exclusive, o.time_start) = (true, Some(from_now(chrono::Duration::hours(1)))));
+    let mut running = Run::new(&server, sink.clone(), dir.path()).id("running").options(|o| o.exclusive = true);
+    registry.insert(waiting.task());
+    registry.insert(running.task());
+
+    let (waiting, waiting_run) = waiting.spawn();
+    running.run().await;
+    tokio::time::timeout(Duration::from_secs(5), waiting_run).await.unwrap().unwrap().unwrap();
+
+    assert_eq!(server.count("/api/ticket/order/createV2"), 1);
+    let finished = sink.events("task_finished");
+    let reason = |task_id: &str| finished.iter().find(|p| p["task_id"] == task_id).unwrap()["reason"].clone();
+    assert_eq!(reason("running"), "order_created");
+    assert_eq!(reason("waiting"), "sibling_succeeded");
+    assert_eq!(waiting.superseded_by().as_deref(), Some("running"));
+    assert_eq!(waiting.record().phase, Phase::Stopped);
+}
+
+/// Stops the task as soon as its order is created, like closing the app
+/// while createV2 is answering, after pausing it first if `pause` is set.
+struct StopOnOrder {
+    events: CollectSink,
+    task: Arc<TaskHandle>,
+    pause: bool,
+}
+
+impl EventSink for StopOnOrder {
+    fn emit(&self, event: &str, payload: Value) -> Result<()> {
+        if event == "order_created" {
+            if self.pause {
+                self.task.pause().unwrap();
+            }
+            self.task.stop();
+        }
+        self.events.emit(event, payload)
+    }
+}
+
+#[tokio::test]
+async fn stopped_task_still_records_its_created_order() {
+    let server = MockServer::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let mut run = Run::new(&server, Arc::new(CollectSink::default()), dir.path());
+    let sink = Arc::new(StopOnOrder { events: CollectSink::default(), task: run.task(), pause: false });
+    run.sink = sink.clone();
+    let task = run.run().await;
+
+    assert!(task.is_stopped());
+    assert_eq!(server.count("/api/ticket/order/getPayParam"), 1);
+    assert_eq!(storage::get_history(dir.path()).unwrap()[0].order_id, "1000000001");
+    assert_eq!(sink.events.events("task_finished")[0]["reason"], "order_created");
+    assert!(!task.order_in_flight());
+}
+
+#[tokio::test]
+async fn task_paused_while_creating_still_fetches_the_payment_when_stopped() {
+    let server = MockServer::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let mut run = Run::new(&server, Arc::new(CollectSink::default()), dir.path());
+    let sink = Arc::new(StopOnOrder { events: CollectSink::default(), task: run.task(), pause: true });
+    run.sink = sink.clone();
+    let task = run.run().await;
+
+    assert_eq!(server.count("/api/ticket/order/getPayParam"), 1);
+    assert_eq!(sink.events.events("payment_ready").len(), 1);
+    assert!(sink.events.events("task_paused").is_empty());
+    assert_eq!(task.record().phase, Phase::Done);
+    assert!(!task.record().paused);
+}
+
+#[tokio::test]
+async fn project_info_merges_linkgoods_and_express_fee() {
+    let server = MockServer::start().unwrap();
+
+    let pool = ClientPool::new(server.endpoints()).unwrap();
+    let res = api::fetch_project_info(&pool.anonymous(), &server.endpoints(), "85939".to_string()).await.unwrap();
+    let project = res.into_data().unwrap();
+    let screens = &project.screen_list;
+
+    assert_eq!(screens.len(), 2);
+    // 380.00 ticket + 10.00 express fee
+    assert_eq!(screens[0].ticket_list[0].price, 39000);
+    assert_eq!(screens[1].link_id.as_deref(), Some("501"));
+    assert_eq!(screens[1].project_id, Some(85940));
+    // Fields the backend doesn't model still reach the frontend
+    let json = serde_json::to_value(&screens[0].ticket_list[0]).unwrap();
+    assert_eq!(json["clickable"], true);
+}
+
+#[tokio::test]
+async fn qrcode_login_then_account_lookup() {
+    let server = MockServer::start().unwrap();
+    server.script_qrcode_poll(ErrnoScript::new().then(86101, 1).then(0, 1));
+    let endpoints = server.endpoints();
+    let pool = ClientPool::new(endpoints.clone()).unwrap();
+
+    let (_, key) = auth::generate_qrcode(&pool.anonymous(), &endpoints).await.unwrap();
+    let cookies: Vec<String> = serde_json::from_str(&auth::poll_login(&pool.anonymous(), &endpoints, &key).await.unwrap()).unwrap();
+    assert!(cookies.iter().any(|c| c.starts_with("SESSDATA=")));
+
+    let account = api::fetch_account(&pool, &endpoints, cookies).await.unwrap();
+    assert_eq!(account.uid, "10001");
+    assert_eq!(account.level, 6);
+    assert!(account.is_vip);
+}
+
+#[tokio::test]
+async fn buyer_and_address_lists() {
+    let server = MockServer::start().unwrap();
+    let endpoints = server.endpoints();
+    let pool = ClientPool::new(endpoints.clone()).unwrap();
+    let client = pool.for_cookies(&["SESSDATA=mock-sessdata; Path=/; HttpOnly".to_string()]).unwrap();
+
+    let buyers = api::fetch_buyers(&client, &endpoints, "85939".to_string()).await.unwrap();
+    assert_eq!(buyers.into_data().unwrap().list.len(), 2);
+
+    let addresses = api::fetch_address_list(&client, &endpoints).await.unwrap();
+    assert_eq!(addresses.into_data().unwrap().addr_list[0].id, 9001);
+    // Set-Cookie attributes are not sent back
+    assert_eq!(server.requests().last().unwrap().cookie.as_deref(), Some("SESSDATA=mock-sessdata"));
+}
+
+#[tokio::test]
+async fn sale_start_is_checked_again_before_firing() {
+    let server = MockServer::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+    let pool = ClientPool::new(server.endpoints()).unwrap();
+    let sale_start = Local::now().timestamp() + 30;
+    server.set_sku_sale_start(2001, sale_start);
+    let mut options = BuyOptions { at_sale_start: true, ..BuyOptions::from_settings(&Settings::default()) };
+    buy::apply_sale_start(&pool.anonymous(), &server.endpoints(), &ticket_info(), &mut options).await.unwrap();
+    let scheduled = Local.timestamp_opt(sale_start, 0).unwrap().format("%Y-%m-%d %H:%M:%S").to_string();
+    assert_eq!(options.time_start, Some(scheduled));
+
+    // Bilibili moves the sale forward; the re-check within the last minute follows it
+    server.set_sku_sale_start(2001, Local::now().timestamp() + 1);
+    let task = Run::new(&server, sink.clone(), dir.path())
+        .options(|o| (o.time_start, o.at_sale_start) = (options.time_start, true))
+        .run()
+        .await;
+
+    assert_eq!(sink.events("task_rescheduled").len(), 1);
+    assert_eq!(task.record().phase, Phase::Done);
+}
+
+#[tokio::test]
+async fn sale_start_does_not_override_the_users_start_time() {
+    let server = MockServer::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+    let sale_start = Local::now() + chrono::Duration::seconds(30);
+    server.set_sku_sale_start(2001, sale_start.timestamp());
+    let (task, run) = Run::new(&server, sink.clone(), dir.path())
+        .options(|o| (o.time_start, o.at_sale_start) = (Some(sale_start.format("%Y-%m-%d %H:%M:%S").to_string()), true))
+        .spawn();
+    // The first re-check, within the last minute already, finds it unchanged
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    server.set_sku_sale_start(2001, Local::now().timestamp() + 20);
+    let mine = from_now(chrono::Duration::seconds(2));
+    task.reschedule(&mine).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), run).await.unwrap().unwrap().unwrap();
+
+    assert_eq!(sink.events("task_rescheduled").len(), 1);
+    let record = task.record();
+    assert_eq!((record.time_start.as_deref(), record.rescheduled, record.phase), (Some(mine.as_str()), true, Phase::Done));
+    assert!(task.logs(&Default::default()).iter().any(|l| l.message == "Keeping the start time set by the user"));
+}
+
+#[tokio::test]
+async fn time_sync_combines_samples_of_click_now() {
+    let server = MockServer::start().unwrap();
+    let pool = ClientPool::new(server.endpoints()).unwrap();
+
+    let sources = timesync::sources(&[], None, &server.endpoints());
+    let clock = Clock::new(pool.anonymous(), Vec::new());
+    let res = api::sync_time(&clock, &sources, 4).await.unwrap();
+    assert_eq!(server.count("/x/report/click/now"), 4);
+    assert_eq!(res["source"], server.endpoints().click_now());
+    // The mock serves the local clock truncated to seconds
+    let (diff, uncertainty) = (res["diff"].as_f64().unwrap(), res["uncertainty"].as_f64().unwrap());
+    assert!(diff.abs() <= uncertainty + 1.0, "{}", res);
+    assert!(uncertainty <= 500.0 + res["rtt"].as_f64().unwrap(), "{}", res);
+    assert_eq!(res["samples"].as_u64().unwrap() + res["discarded"].as_u64().unwrap(), 4);
+}
+
+#[tokio::test]
+async fn time_sources_are_tried_in_order() {
+    let server = MockServer::start().unwrap();
+    let pool = ClientPool::new(server.endpoints()).unwrap();
+    let click_now = server.endpoints().click_now();
+    let configured = [
+        TimeSource { url: click_now.clone(), pointer: Some("/data/then".to_string()), unit: TimeUnit::Seconds, date_header: false },
+        TimeSource { url: click_now.clone(), pointer: None, unit: TimeUnit::Millis, date_header: true },
+    ];
+
+    let sync = timesync::measure_first(&pool.anonymous(), &configured, 2).await.unwrap();
+    assert_eq!(sync.source, click_now);
+    // One failed sample of the first source, two of the Date header
+    assert_eq!(server.count("/x/report/click/now"), 3);
+    assert!(sync.offset_ms.abs() <= sync.uncertainty_ms + 1.0, "{:?}", sync);
+
+    let err = timesync::measure_first(&pool.anonymous(), &configured[..1], 2).await.unwrap_err();
+    assert!(err.to_string().contains("/data/then"), "{}", err);
+}
+
+#[tokio::test]
+async fn shared_clock_refreshes_only_while_tasks_wait() {
+    let server = MockServer::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+    let pool = ClientPool::new(server.endpoints()).unwrap();
+    let clock = Clock::new(pool.anonymous(), timesync::sources(&[], None, &server.endpoints()));
+    clock.set_refresh_interval(Duration::from_millis(200));
+    let refresh = tokio::spawn(clock.clone().refresh());
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(server.count("/x/report/click/now"), 0);
+
+    let time_start = from_now(chrono::Duration::seconds(3));
+    let runs: Vec<_> = (0..2)
+        .map(|_| {
+            Run::new(&server, sink.clone(), dir.path())
+                .options(|o| o.time_start = Some(time_start.clone()))
+                .clock(clock.clone())
+                .spawn()
+        })
+        .collect();
+    for (task, run) in runs {
+        tokio::time::timeout(Duration::from_secs(6), run).await.unwrap().unwrap().unwrap();
+        assert_eq!(task.record().phase, Phase::Done);
+    }
+    assert!(clock.offset_ms().is_some());
+
+    // Whole syncs only, one at a time for both tasks
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let requests = server.count("/x/report/click/now");
+    assert!(requests > 0 && requests.is_multiple_of(timesync::DEFAULT_SAMPLES as usize), "{}", requests);
+    assert!(requests <= 3 * timesync::DEFAULT_SAMPLES as usize, "{}", requests);
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert_eq!(server.count("/x/report/click/now"), requests);
+    refresh.abort();
+}
+
+#[tokio::test]
+async fn clock_refresh_follows_the_sync_interval() {
+    let server = MockServer::start().unwrap();
+    let pool = ClientPool::new(server.endpoints()).unwrap();
+    let clock = Clock::new(pool.anonymous(), timesync::sources(&[], None, &server.endpoints()));
+    let _waiting = clock.subscribe();
+    let refresh = tokio::spawn(clock.clone().refresh());
+
+    // Off by default, i.e. a sync interval of 0
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(server.count("/x/report/click/now"), 0);
+
+    clock.set_refresh_interval(Duration::from_millis(50));
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while clock.offset_ms().is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    refresh.abort();
+}
+
+#[tokio::test]
+async fn clock_offset_takes_over_from_the_task_offset() {
+    let server = MockServer::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(CollectSink::default());
+    let pool = ClientPool::new(server.endpoints()).unwrap();
+    // The server is an hour ahead, so a start an hour from now is due already
+    let clock = Clock::new(pool.anonymous(), Vec::new());
+    clock.set_offset(3_600_000 + 5000);
+    let task = Run::new(&server, sink.clone(), dir.path())
+        .options(|o| o.time_start = Some(from_now(chrono::Duration::hours(1))))
+        .clock(clock)
+        .run()
+        .await;
+
+    assert_eq!(sink.events("task_scheduled")[0]["offset_ms"], 3_605_000);
+    assert_eq!(task.record().phase, Phase::Done);
+}
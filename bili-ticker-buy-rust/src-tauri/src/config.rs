@@ -1,3 +0,0 @@
-// Config management placeholder
-#[allow(dead_code)]
-pub struct Config;
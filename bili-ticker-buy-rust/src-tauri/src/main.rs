@@ -3,26 +3,50 @@
     windows_subsystem = "windows"
 )]
 
-mod auth;
-mod buy;
-mod config;
-mod util;
-mod api;
-mod storage;
-
+use bili_ticket_core::{api, auth, buy, config, logfile, logs, storage, timesync};
+use bili_ticket_core::clock::Clock;
+use bili_ticket_core::error::{Error, Result, StorageContext};
+use bili_ticket_core::event::EventSink;
+use bili_ticket_core::http::ClientPool;
+use bili_ticket_core::logs::{ExportFormat, LogEntry, LogLevel, LogQuery};
+use bili_ticket_core::models::{AddressList, ApiResponse, BuyerList, Project, UserNav};
+use bili_ticket_core::phase::Phase;
+use bili_ticket_core::task::{split_missed, TaskHandle, TaskRecord, TaskRegistry};
 use tauri::Manager;
-use buy::TicketInfo;
-use storage::{Account, HistoryItem, ProjectConfig};
+use buy::{BuyOptions, TicketInfo};
+use config::Settings;
+use storage::{Account, HistoryItem, ProjectConfig, StoredTask};
 use std::fs;
 use std::path::PathBuf;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use uuid::Uuid;
 
+/// How long exiting waits for running tasks, so a createV2 or getPayParam
+/// request already sent can be answered and recorded to history.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Forwards core task events to the main window. Tasks may outlive a
+/// window reload or be restored at startup, so the sink holds the app
+/// handle rather than a window.
+struct MainWindowSink(tauri::AppHandle);
+
+impl EventSink for MainWindowSink {
+    fn emit(&self, event: &str, payload: serde_json::Value) -> Result<()> {
+        self.0.emit_to("main", event, payload).map_err(|e| Error::Internal(format!("Failed to emit {}: {}", event, e)))
+    }
+}
 
 struct AppState {
-    tasks: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
+    tasks: TaskRegistry,
+    config: config::Config,
+    settings: Mutex<Settings>,
+    clients: ClientPool,
+    /// Server time offset shared by every task
+    clock: Clock,
+    /// Set once the app starts shutting down
+    exiting: Arc<AtomicBool>,
 }
 
 fn get_app_dir(app_handle: &tauri::AppHandle) -> PathBuf {
@@ -39,239 +63,336 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-fn save_cookies(app_handle: tauri::AppHandle, cookies: String) -> Result<(), String> {
+fn save_cookies(app_handle: tauri::AppHandle, cookies: String) -> Result<()> {
     let dir = get_app_dir(&app_handle);
-    storage::save_cookies(&dir, cookies).map_err(|e| e.to_string())
+    storage::save_cookies(&dir, cookies)
 }
 
 #[tauri::command]
-fn load_cookies(app_handle: tauri::AppHandle) -> Result<String, String> {
+fn load_cookies(app_handle: tauri::AppHandle) -> Result<String> {
     let dir = get_app_dir(&app_handle);
-    storage::load_cookies(&dir).map_err(|e| e.to_string())
+    storage::load_cookies(&dir)
 }
 
 #[tauri::command]
-fn get_accounts(app_handle: tauri::AppHandle) -> Result<Vec<Account>, String> {
+fn get_accounts(app_handle: tauri::AppHandle) -> Result<Vec<Account>> {
     let dir = get_app_dir(&app_handle);
-    storage::get_accounts(&dir).map_err(|e| e.to_string())
+    storage::get_accounts(&dir)
 }
 
 #[tauri::command]
-async fn add_account(app_handle: tauri::AppHandle, cookies: Vec<String>) -> Result<Account, String> {
-    // Fetch user info to get uid, name, face
-    let res = api::fetch_user_info(cookies.clone()).await.map_err(|e| e.to_string())?;
-    
-    if res["code"].as_i64().unwrap_or(-1) != 0 {
-        return Err("Invalid cookies".to_string());
-    }
-
-    let data = &res["data"];
-    
-    let level = data["level_info"]["current_level"].as_i64().unwrap_or(0) as i32;
-    let is_vip = data["vipStatus"].as_i64().unwrap_or(0) == 1;
-    let coins = data["money"].as_f64().unwrap_or(0.0);
-
-    let account = Account {
-        uid: data["mid"].to_string(),
-        name: data["uname"].as_str().unwrap_or("").to_string(),
-        face: data["face"].as_str().unwrap_or("").to_string(),
-        cookies,
-        level,
-        is_vip,
-        coins,
-    };
+async fn add_account(state: tauri::State<'_, AppState>, app_handle: tauri::AppHandle, cookies: Vec<String>) -> Result<Account> {
+    let account = api::fetch_account(&state.clients, &state.config.endpoints, cookies).await?;
 
     let dir = get_app_dir(&app_handle);
 
     // Load existing accounts
-    let mut accounts = storage::get_accounts(&dir).map_err(|e| e.to_string())?;
+    let mut accounts = storage::get_accounts(&dir)?;
     
     // Remove existing if same uid
     accounts.retain(|a| a.uid != account.uid);
     accounts.push(account.clone());
 
     // Save
-    storage::save_accounts(&dir, &accounts).map_err(|e| e.to_string())?;
+    storage::save_accounts(&dir, &accounts)?;
 
     Ok(account)
 }
 
 #[tauri::command]
-fn remove_account(app_handle: tauri::AppHandle, uid: String) -> Result<(), String> {
+fn remove_account(state: tauri::State<'_, AppState>, app_handle: tauri::AppHandle, uid: String) -> Result<()> {
     let dir = get_app_dir(&app_handle);
-    let mut accounts = storage::get_accounts(&dir).map_err(|e| e.to_string())?;
+    let mut accounts = storage::get_accounts(&dir)?;
     accounts.retain(|a| a.uid != uid);
-    storage::save_accounts(&dir, &accounts).map_err(|e| e.to_string())?;
+    storage::save_accounts(&dir, &accounts)?;
+    state.clients.evict(&uid);
     Ok(())
 }
 
 #[tauri::command]
-fn get_history(app_handle: tauri::AppHandle) -> Result<Vec<HistoryItem>, String> {
+fn get_history(app_handle: tauri::AppHandle) -> Result<Vec<HistoryItem>> {
     let dir = get_app_dir(&app_handle);
-    storage::get_history(&dir).map_err(|e| e.to_string())
+    storage::get_history(&dir)
 }
 
 #[tauri::command]
-fn add_history(app_handle: tauri::AppHandle, item: HistoryItem) -> Result<(), String> {
+fn add_history(app_handle: tauri::AppHandle, item: HistoryItem) -> Result<()> {
     let dir = get_app_dir(&app_handle);
-    storage::add_history_item(&dir, item).map_err(|e| e.to_string())
+    storage::add_history_item(&dir, item)
 }
 
 #[tauri::command]
-fn clear_history(app_handle: tauri::AppHandle) -> Result<(), String> {
+fn clear_history(app_handle: tauri::AppHandle) -> Result<()> {
     let dir = get_app_dir(&app_handle);
-    storage::clear_history(&dir).map_err(|e| e.to_string())
+    storage::clear_history(&dir)
 }
 
 #[tauri::command]
-fn get_project_history(app_handle: tauri::AppHandle) -> Result<Vec<ProjectConfig>, String> {
+fn get_project_history(app_handle: tauri::AppHandle) -> Result<Vec<ProjectConfig>> {
     let dir = get_app_dir(&app_handle);
-    storage::get_project_history(&dir).map_err(|e| e.to_string())
+    storage::get_project_history(&dir)
 }
 
 #[tauri::command]
-fn add_project_history(app_handle: tauri::AppHandle, item: ProjectConfig) -> Result<(), String> {
+fn add_project_history(app_handle: tauri::AppHandle, item: ProjectConfig) -> Result<()> {
     let dir = get_app_dir(&app_handle);
-    storage::add_project_history(&dir, item).map_err(|e| e.to_string())
+    storage::add_project_history(&dir, item)
 }
 
 #[tauri::command]
-fn remove_project_history(app_handle: tauri::AppHandle, project_id: String, sku_id: String) -> Result<(), String> {
+fn remove_project_history(app_handle: tauri::AppHandle, project_id: String, sku_id: String) -> Result<()> {
     let dir = get_app_dir(&app_handle);
-    storage::remove_project_history_item(&dir, project_id, sku_id).map_err(|e| e.to_string())
+    storage::remove_project_history_item(&dir, project_id, sku_id)
 }
 
 #[tauri::command]
-async fn get_user_info(cookies: Vec<String>) -> Result<serde_json::Value, String> {
-    api::fetch_user_info(cookies).await.map_err(|e| e.to_string())
+async fn get_user_info(state: tauri::State<'_, AppState>, cookies: Vec<String>) -> Result<ApiResponse<UserNav>> {
+    let client = state.clients.for_cookies(&cookies)?;
+    api::fetch_user_info(&client, &state.config.endpoints).await
 }
 
 #[tauri::command]
-async fn get_login_qrcode() -> Result<(String, String), String> {
-    auth::generate_qrcode().await.map_err(|e| e.to_string())
+async fn get_login_qrcode(state: tauri::State<'_, AppState>) -> Result<(String, String)> {
+    auth::generate_qrcode(&state.clients.anonymous(), &state.config.endpoints).await
 }
 
 #[tauri::command]
-async fn poll_login_status(qrcode_key: String) -> Result<String, String> {
-    auth::poll_login(&qrcode_key).await.map_err(|e| e.to_string())
+async fn poll_login_status(state: tauri::State<'_, AppState>, qrcode_key: String) -> Result<String> {
+    auth::poll_login(&state.clients.anonymous(), &state.config.endpoints, &qrcode_key).await
 }
 
 #[tauri::command]
-async fn fetch_project(id: String) -> Result<serde_json::Value, String> {
-    api::fetch_project_info(id).await.map_err(|e| e.to_string())
+async fn fetch_project(state: tauri::State<'_, AppState>, id: String) -> Result<ApiResponse<Project>> {
+    api::fetch_project_info(&state.clients.anonymous(), &state.config.endpoints, id).await
 }
 
 #[tauri::command]
-async fn fetch_buyer_list(project_id: String, cookies: Vec<String>) -> Result<serde_json::Value, String> {
-    api::fetch_buyers(project_id, cookies).await.map_err(|e| e.to_string())
+async fn fetch_buyer_list(state: tauri::State<'_, AppState>, project_id: String, cookies: Vec<String>) -> Result<ApiResponse<BuyerList>> {
+    let client = state.clients.for_cookies(&cookies)?;
+    api::fetch_buyers(&client, &state.config.endpoints, project_id).await
 }
 
 #[tauri::command]
-async fn fetch_address_list(cookies: Vec<String>) -> Result<serde_json::Value, String> {
-    api::fetch_address_list(cookies).await.map_err(|e| e.to_string())
+async fn fetch_address_list(state: tauri::State<'_, AppState>, cookies: Vec<String>) -> Result<ApiResponse<AddressList>> {
+    let client = state.clients.for_cookies(&cookies)?;
+    api::fetch_address_list(&client, &state.config.endpoints).await
 }
 
 #[tauri::command]
-async fn sync_time(server_url: Option<String>) -> Result<serde_json::Value, String> {
-    let url = server_url.unwrap_or_else(|| "https://api.bilibili.com/x/report/click/now".to_string());
-
-    let server_time = if url.starts_with("http") {
-        api::get_server_time(Some(url)).await.map_err(|e| e.to_string())?
-    } else {
-        // Wrap blocking NTP call in spawn_blocking to avoid blocking the async runtime
-        let ntp_url = url.clone();
-        tokio::task::spawn_blocking(move || {
-            api::get_ntp_time(&ntp_url).map(|t| t as i64)
-        }).await.map_err(|e| e.to_string())?.map_err(|e| e.to_string())?
+async fn sync_time(state: tauri::State<'_, AppState>, server_url: Option<String>, samples: Option<u32>) -> Result<serde_json::Value> {
+    let samples = samples.unwrap_or(timesync::DEFAULT_SAMPLES);
+    let sources = {
+        let settings = state.settings.lock().unwrap();
+        // The server asked for goes first, the configured ones are the fallback
+        let requested = server_url.filter(|s| !s.trim().is_empty()).map(|s| timesync::TimeSource::from_url(&s));
+        let configured: Vec<_> = requested.into_iter().chain(settings.time_sources.iter().cloned()).collect();
+        timesync::sources(&configured, settings.ntp_server.as_deref(), &state.config.endpoints)
     };
+    api::sync_time(&state.clock, &sources, samples).await
+}
 
-    let local_time = api::get_local_time();
-    let diff = server_time - local_time;
-    
-    Ok(serde_json::json!({
-        "diff": diff,
-        "server": server_time,
-        "local": local_time
-    }))
+/// Where the shared clock syncs from unless told otherwise.
+fn clock_sources(settings: &Settings, endpoints: &config::Endpoints) -> Vec<timesync::TimeSource> {
+    timesync::sources(&settings.time_sources, settings.ntp_server.as_deref(), endpoints)
 }
 
+#[tauri::command]
+fn get_settings(state: tauri::State<'_, AppState>) -> Settings {
+    state.settings.lock().unwrap().clone()
+}
+
+/// Merge `patch` into the current settings and save them.
+#[tauri::command]
+fn update_settings(state: tauri::State<'_, AppState>, app_handle: tauri::AppHandle, patch: serde_json::Value) -> Result<Settings> {
+    let dir = get_app_dir(&app_handle);
+    let mut settings = state.settings.lock().unwrap();
+    let updated = settings.merged(patch)?;
+    updated.save(&dir)?;
+    logfile::set_level(&updated.logging);
+    state.clock.set_sources(clock_sources(&updated, &state.config.endpoints));
+    state.clock.set_refresh_interval(Duration::from_millis(updated.sync_interval));
+    *settings = updated.clone();
+    Ok(updated)
+}
+
+/// Options left out fall back to the saved settings.
 #[tauri::command]
 async fn start_buy(
     state: tauri::State<'_, AppState>,
-    window: tauri::Window, 
+    app_handle: tauri::AppHandle,
     ticket_info: String, 
-    interval: u64, 
-    mode: u32, 
-    total_attempts: u32,
+    interval: Option<u64>, 
+    mode: Option<u32>, 
+    total_attempts: Option<u32>,
     time_start: Option<String>,
     proxy: Option<String>,
     time_offset: Option<f64>,
     buyers: Option<Vec<serde_json::Value>>,
-    ntp_server: Option<String>
-) -> Result<String, String> {
-    // Filter out empty time_start
-    let time_start = time_start.filter(|s| !s.trim().is_empty());
+    ntp_server: Option<String>,
+    exclusive: Option<bool>,
+    watch: Option<bool>,
+    watch_interval: Option<u64>,
+    at_sale_start: Option<bool>,
+    time_end: Option<String>,
+    max_prepare_failures: Option<u32>,
+    max_requests: Option<u32>,
+    stop_errnos: Option<Vec<i64>>
+) -> Result<String> {
+    let defaults = BuyOptions::from_settings(&state.settings.lock().unwrap());
+    let mut options = BuyOptions {
+        interval: interval.unwrap_or(defaults.interval),
+        mode: mode.unwrap_or(defaults.mode),
+        total_attempts: total_attempts.unwrap_or(defaults.total_attempts),
+        time_start,
+        proxy: proxy.filter(|p| !p.trim().is_empty()).or(defaults.proxy),
+        time_offset,
+        ntp_server: ntp_server.filter(|s| !s.trim().is_empty()).or(defaults.ntp_server),
+        time_sources: defaults.time_sources,
+        exclusive: exclusive.unwrap_or(defaults.exclusive),
+        watch: watch.unwrap_or(defaults.watch),
+        watch_interval: watch_interval.unwrap_or(defaults.watch_interval),
+        at_sale_start: at_sale_start.unwrap_or(defaults.at_sale_start),
+        time_end: time_end.filter(|t| !t.trim().is_empty()),
+        max_prepare_failures: max_prepare_failures.unwrap_or(defaults.max_prepare_failures),
+        max_requests: max_requests.unwrap_or(defaults.max_requests),
+        stop_errnos: stop_errnos.unwrap_or(defaults.stop_errnos),
+    };
 
-    let mut info: TicketInfo = serde_json::from_str(&ticket_info).map_err(|e| e.to_string())?;
+    let mut info: TicketInfo = serde_json::from_str(&ticket_info)
+        .map_err(|e| Error::InvalidInput(format!("Invalid ticket info: {}", e)))?;
     
     // If buyers are provided from UI, override the one in ticket_info
     if let Some(b) = buyers {
-        if !b.is_empty() {
-            info.buyer_info = serde_json::Value::Array(b.clone());
-
-            // Ensure contact info is present and not empty
-            let contact_name_missing = info.contact_name.as_ref().map(|s| s.is_empty()).unwrap_or(true);
-            let contact_tel_missing = info.contact_tel.as_ref().map(|s| s.is_empty()).unwrap_or(true);
-
-            if contact_name_missing || contact_tel_missing {
-                 if let Some(first) = b.first() {
-                     if contact_name_missing {
-                         if let Some(name) = first["name"].as_str() {
-                             if !name.is_empty() {
-                                 info.contact_name = Some(name.to_string());
-                             }
-                         }
-                     }
-                     if contact_tel_missing {
-                         // Try different fields for phone
-                         let tel = first["tel"].as_str()
-                             .or(first["mobile"].as_str())
-                             .or(first["phone"].as_str());
-                         
-                         if let Some(t) = tel {
-                             if !t.is_empty() && !t.contains('*') {
-                                 info.contact_tel = Some(t.to_string());
-                             }
-                         }
-                     }
-                 }
-            }
-        }
+        info.apply_buyers(b);
     }
+    buy::apply_sale_start(&state.clients.anonymous(), &state.config.endpoints, &info, &mut options).await?;
 
     let task_id = Uuid::new_v4().to_string();
-    let stop_flag = Arc::new(AtomicBool::new(false));
-    
-    state.tasks.lock().unwrap().insert(task_id.clone(), stop_flag.clone());
+    spawn_task(&state, &app_handle, task_id.clone(), info, options)?;
+    Ok(task_id)
+}
+
+/// Register a task and run it in the background. Scheduled tasks are kept
+/// in tasks.json until they finish, so they can be re-armed after a restart.
+fn spawn_task(state: &AppState, app_handle: &tauri::AppHandle, task_id: String, info: TicketInfo, options: BuyOptions) -> Result<()> {
+    let app_dir = get_app_dir(app_handle);
+    let client = state.clients.for_cookies(&info.cookies)?;
+
+    let scheduled = options.time_start.as_deref().is_some_and(|s| !s.trim().is_empty());
+    if scheduled {
+        storage::save_task(&app_dir, StoredTask {
+            task_id: task_id.clone(),
+            ticket_info: info.clone(),
+            options: options.clone(),
+        })?;
+    }
 
-    // Resolve app directory for the background task
-    let app_dir = get_app_dir(&window.app_handle());
+    let task = TaskHandle::new(TaskRecord::new(task_id.clone(), &info, &options));
+    state.tasks.insert(task.clone());
 
-    let task_id_clone = task_id.clone();
-    let tasks_clone = state.tasks.clone();
+    let sink: Arc<dyn EventSink> = Arc::new(MainWindowSink(app_handle.clone()));
+    let endpoints = state.config.endpoints.clone();
+    let clock = state.clock.clone();
+    let exiting = state.exiting.clone();
+
+    // Finished tasks stay in the registry until the window removes them
     tauri::async_runtime::spawn(async move {
-        if let Err(e) = buy::start_buy_task(window, task_id_clone.clone(), stop_flag, info, interval, mode, total_attempts, time_start, proxy, time_offset, ntp_server, app_dir).await {
-            println!("Buy task error: {}", e);
+        if let Err(e) = buy::start_buy_task(sink, client, endpoints, clock, task.clone(), info, options, app_dir.clone()).await {
+            log::error!("[task {}] Buy task error: {}", task_id, e);
+        }
+        // Stopped by exiting: keep it in tasks.json to re-arm on the next start
+        let kept = exiting.load(Ordering::Relaxed) && task.record().phase == Phase::Stopped;
+        if scheduled && !kept {
+            if let Err(e) = storage::remove_task(&app_dir, &task_id) {
+                log::warn!("Failed to remove finished task {} from tasks.json: {}", task_id, e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Re-arm saved tasks whose start time is still ahead, and keep the ones
+/// that missed it as failed records so the window can report them.
+fn restore_tasks(state: &AppState, app_handle: &tauri::AppHandle) {
+    let dir = get_app_dir(app_handle);
+    let saved = match storage::get_tasks(&dir) {
+        Ok(saved) => saved,
+        Err(e) => {
+            log::warn!("Failed to load saved tasks: {}", e);
+            return;
+        }
+    };
+
+    let (pending, missed) = split_missed(saved, chrono::Local::now());
+    for task in missed {
+        log::warn!("Task {} missed its start time {:?}", task.task_id, task.options.time_start);
+        state.tasks.insert(TaskHandle::new(TaskRecord::missed(&task)));
+        if let Err(e) = storage::remove_task(&dir, &task.task_id) {
+            log::warn!("Failed to remove missed task {} from tasks.json: {}", task.task_id, e);
+        }
+    }
+    for task in pending {
+        log::info!("Re-arming task {} for {:?}", task.task_id, task.options.time_start);
+        if let Err(e) = spawn_task(state, app_handle, task.task_id.clone(), task.ticket_info, task.options) {
+            log::warn!("Failed to re-arm task {}: {}", task.task_id, e);
+        }
+    }
+}
+
+/// Start exiting, first asking the user when a task may be placing an
+/// order right now.
+fn request_exit(app: &tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    if state.exiting.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let ordering = state.tasks.orders_in_flight();
+    if ordering.is_empty() {
+        tauri::async_runtime::spawn(shutdown(app.clone()));
+        return;
+    }
+
+    let projects: Vec<String> = ordering.iter()
+        .map(|r| r.project_name.clone().unwrap_or_else(|| r.project_id.clone()))
+        .collect();
+    log::warn!("Exit requested while {} task(s) are placing an order: {}", ordering.len(), projects.join(", "));
+    let message = format!(
+        "{} 个任务正在下单或获取支付信息 ({})。\n退出前会等待最多 {} 秒，但订单仍可能未记录到历史中，请退出后到 B 站订单列表确认。\n\n仍要退出吗？",
+        ordering.len(), projects.join("、"), SHUTDOWN_GRACE.as_secs()
+    );
+    let window = app.get_window("main");
+    let app = app.clone();
+    tauri::api::dialog::confirm(window.as_ref(), "订单处理中", message, move |quit| {
+        if quit {
+            tauri::async_runtime::spawn(shutdown(app));
+        } else {
+            app.state::<AppState>().exiting.store(false, Ordering::Relaxed);
         }
-        // Clean up the task from AppState to prevent memory leak
-        tasks_clone.lock().unwrap().remove(&task_id_clone);
     });
-    
-    Ok(task_id)
+}
+
+/// Stop every task, give in-flight requests [`SHUTDOWN_GRACE`] to finish,
+/// then flush storage and exit.
+async fn shutdown(app: tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    log::info!("Shutting down, stopping {} task(s)", state.tasks.list().iter().filter(|r| !r.phase.is_terminal()).count());
+    state.tasks.stop_all();
+    for record in state.tasks.wait_finished(SHUTDOWN_GRACE).await {
+        log::warn!("[task {}] Still in phase {:?} at exit", record.task_id, record.phase);
+    }
+
+    match storage::flush(&get_app_dir(&app)) {
+        Ok(0) => {}
+        Ok(n) => log::info!("Removed {} unfinished temp file(s)", n),
+        Err(e) => log::warn!("Failed to flush storage: {}", e),
+    }
+    log::info!("Exiting");
+    log::logger().flush();
+    app.exit(0);
 }
 
 #[tauri::command]
-async fn open_bilibili_home(app: tauri::AppHandle, cookies: Vec<String>) -> Result<(), String> {
+async fn open_bilibili_home(app: tauri::AppHandle, cookies: Vec<String>) -> Result<()> {
     let cookie_script = cookies.iter().map(|c| {
         // Extract key=value from Set-Cookie string (which might contain attributes like HttpOnly)
         let key_val = c.split(';').next().unwrap_or("").trim();
@@ -323,80 +444,171 @@ async fn open_bilibili_home(app: tauri::AppHandle, cookies: Vec<String>) -> Resu
         .initialization_script(&init_script)
         .inner_size(1280.0, 800.0)
         .build()
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| Error::Internal(format!("Failed to open window: {}", e)))?;
 
     Ok(())
 }
 
 #[tauri::command]
-fn export_cookie(app_handle: tauri::AppHandle, uid: String, path: String) -> Result<(), String> {
+fn export_cookie(app_handle: tauri::AppHandle, uid: String, path: String) -> Result<()> {
     let dir = get_app_dir(&app_handle);
-    let accounts = storage::get_accounts(&dir).map_err(|e| e.to_string())?;
-    let account = accounts.iter().find(|a| a.uid == uid).ok_or("Account not found")?;
-
-    let mut cookie_items = Vec::new();
-    for c in &account.cookies {
-        // c is like "name=value; ..."
-        let parts: Vec<&str> = c.split(';').collect();
-        if let Some(first) = parts.first() {
-            if let Some((name, value)) = first.split_once('=') {
-                cookie_items.push(serde_json::json!({
-                    "name": name.trim(),
-                    "value": value.trim()
-                }));
-            }
-        }
-    }
-
-    let json_data = serde_json::json!({
-        "_default": {
-            "1": {
-                "key": "cookie",
-                "value": cookie_items
-            }
-        }
-    });
+    let accounts = storage::get_accounts(&dir)?;
+    let account = accounts.iter().find(|a| a.uid == uid).ok_or_else(|| Error::NotFound(format!("Account {} not found", uid)))?;
 
-    let content = serde_json::to_string_pretty(&json_data).map_err(|e| e.to_string())?;
-    fs::write(path, content).map_err(|e| e.to_string())?;
+    let json_data = storage::export_cookie_json(&account.cookies);
+    let content = serde_json::to_string_pretty(&json_data)
+        .map_err(|e| Error::Internal(e.to_string()))?;
+    fs::write(&path, content).storage_context(|| format!("Failed to write {}", path))?;
     Ok(())
 }
 
 #[tauri::command]
-async fn import_cookie(app_handle: tauri::AppHandle, path: String) -> Result<(), String> {
-    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
-    let json: serde_json::Value = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+async fn import_cookie(state: tauri::State<'_, AppState>, app_handle: tauri::AppHandle, path: String) -> Result<()> {
+    let content = fs::read_to_string(&path).storage_context(|| format!("Failed to read {}", path))?;
+    let cookies = storage::import_cookie_json(&content)?;
 
-    let items = json["_default"]["1"]["value"].as_array().ok_or("Invalid format: missing _default.1.value")?;
-    
-    let mut cookies = Vec::new();
-    for item in items {
-        let name = item["name"].as_str().unwrap_or("");
-        let value = item["value"].as_str().unwrap_or("");
-        if !name.is_empty() {
-            cookies.push(format!("{}={}", name, value));
-        }
+    add_account(state, app_handle, cookies).await.map(|_| ())
+}
+
+#[tauri::command]
+fn stop_task(state: tauri::State<'_, AppState>, task_id: String) -> Result<()> {
+    if let Some(task) = state.tasks.get(&task_id) {
+        task.stop();
     }
+    Ok(())
+}
+
+/// Hold a running task before its next request.
+#[tauri::command]
+fn pause_task(state: tauri::State<'_, AppState>, task_id: String) -> Result<()> {
+    state.tasks.get(&task_id)
+        .ok_or_else(|| Error::NotFound(format!("Task {} not found", task_id)))?
+        .pause()
+}
 
-    if cookies.is_empty() {
-        return Err("No cookies found in file".to_string());
+#[tauri::command]
+fn resume_task(state: tauri::State<'_, AppState>, task_id: String) -> Result<()> {
+    state.tasks.get(&task_id)
+        .ok_or_else(|| Error::NotFound(format!("Task {} not found", task_id)))?
+        .resume()
+}
+
+/// Move the start time of a task that is still counting down.
+#[tauri::command]
+fn reschedule_task(app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>, task_id: String, new_time: String) -> Result<()> {
+    let task = state.tasks.get(&task_id)
+        .ok_or_else(|| Error::NotFound(format!("Task {} not found", task_id)))?;
+    task.reschedule(&new_time)?;
+
+    let dir = get_app_dir(&app_handle);
+    if let Some(mut saved) = storage::get_tasks(&dir)?.into_iter().find(|t| t.task_id == task_id) {
+        saved.options.time_start = Some(new_time);
+        // Restored with the user's time rather than the published sale start
+        saved.options.at_sale_start = false;
+        storage::save_task(&dir, saved)?;
     }
+    Ok(())
+}
 
-    add_account(app_handle, cookies).await.map(|_| ())
+/// Lines from a task's log, oldest first. See [`LogQuery`] for the filters.
+#[tauri::command]
+fn get_task_logs(
+    state: tauri::State<'_, AppState>,
+    task_id: String,
+    since: Option<u64>,
+    limit: Option<usize>,
+    level: Option<LogLevel>,
+    phase: Option<Phase>,
+) -> Result<Vec<LogEntry>> {
+    let task = state.tasks.get(&task_id)
+        .ok_or_else(|| Error::NotFound(format!("Task {} not found", task_id)))?;
+    Ok(task.logs(&LogQuery { since, limit, level, phase }))
 }
 
+/// Write a task's log to `path` as text or JSON.
 #[tauri::command]
-fn stop_task(state: tauri::State<'_, AppState>, task_id: String) -> Result<(), String> {
-    if let Some(flag) = state.tasks.lock().unwrap().get(&task_id) {
-        flag.store(true, Ordering::Relaxed);
+fn export_task_logs(
+    state: tauri::State<'_, AppState>,
+    task_id: String,
+    path: String,
+    format: ExportFormat,
+    level: Option<LogLevel>,
+    phase: Option<Phase>,
+) -> Result<()> {
+    let task = state.tasks.get(&task_id)
+        .ok_or_else(|| Error::NotFound(format!("Task {} not found", task_id)))?;
+    let entries = task.logs(&LogQuery { level, phase, ..Default::default() });
+    let content = logs::export(&entries, format)?;
+    fs::write(&path, content).storage_context(|| format!("Failed to write {}", path))?;
+    Ok(())
+}
+
+#[tauri::command]
+fn list_tasks(state: tauri::State<'_, AppState>) -> Vec<TaskRecord> {
+    state.tasks.list()
+}
+
+#[tauri::command]
+fn get_task(state: tauri::State<'_, AppState>, task_id: String) -> Result<TaskRecord> {
+    state.tasks.get(&task_id)
+        .map(|task| task.record())
+        .ok_or_else(|| Error::NotFound(format!("Task {} not found", task_id)))
+}
+
+/// Forget a task, stopping it first if it is still running.
+#[tauri::command]
+fn remove_task(state: tauri::State<'_, AppState>, task_id: String) -> Result<()> {
+    if let Some(task) = state.tasks.remove(&task_id) {
+        task.stop();
     }
     Ok(())
 }
 
 fn main() {
     tauri::Builder::default()
-        .manage(AppState {
-            tasks: Arc::new(Mutex::new(HashMap::new())),
+        .setup(|app| {
+            let dir = get_app_dir(&app.handle());
+            // Settings first, as they hold the log level
+            let settings = Settings::load(&dir);
+            let logging = settings.as_ref().map(|s| s.logging.clone()).unwrap_or_default();
+            if let Err(e) = logfile::init(&dir.join("logs"), &logging) {
+                eprintln!("Failed to set up log files: {}", e);
+            }
+            log::info!("Starting {} v{}", app.package_info().name, app.package_info().version);
+            // Left behind if the last run was killed mid-write
+            if let Err(e) = storage::flush(&dir) {
+                log::warn!("Failed to clean up temp files: {}", e);
+            }
+
+            let settings = settings.unwrap_or_else(|e| {
+                log::warn!("Failed to load settings, using defaults: {}", e);
+                Settings::default()
+            });
+            let config = config::Config::load(&dir).unwrap_or_else(|e| {
+                log::warn!("Failed to load config, using defaults: {}", e);
+                config::Config::default()
+            });
+            let clients = ClientPool::new(config.endpoints.clone())?;
+            let clock = Clock::new(clients.anonymous(), clock_sources(&settings, &config.endpoints));
+            clock.set_refresh_interval(Duration::from_millis(settings.sync_interval));
+            tauri::async_runtime::spawn(clock.clone().refresh());
+            // Synced once up front, so tasks started before any manual sync use it
+            let startup = clock.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = startup.sync().await {
+                    log::warn!("Initial time sync failed: {}", e);
+                }
+            });
+            app.manage(AppState {
+                tasks: TaskRegistry::default(),
+                config,
+                settings: Mutex::new(settings),
+                clients,
+                clock,
+                exiting: Arc::new(AtomicBool::new(false)),
+            });
+            restore_tasks(&app.state::<AppState>(), &app.handle());
+            Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             greet, 
@@ -404,10 +616,20 @@ fn main() {
             poll_login_status, 
             start_buy,
             stop_task,
+            reschedule_task,
+            pause_task,
+            resume_task,
+            get_task_logs,
+            export_task_logs,
+            list_tasks,
+            get_task,
+            remove_task,
             fetch_project,
             fetch_buyer_list,
             fetch_address_list,
             sync_time,
+            get_settings,
+            update_settings,
             save_cookies,
             load_cookies,
             get_user_info,
@@ -424,6 +646,17 @@ fn main() {
             export_cookie,
             import_cookie
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| match event {
+            tauri::RunEvent::WindowEvent { label, event: tauri::WindowEvent::CloseRequested { api, .. }, .. } if label == "main" => {
+                api.prevent_close();
+                request_exit(app);
+            }
+            tauri::RunEvent::ExitRequested { api, .. } => {
+                api.prevent_exit();
+                request_exit(app);
+            }
+            _ => {}
+        });
 }
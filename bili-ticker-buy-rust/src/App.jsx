@@ -3,8 +3,9 @@ import { invoke } from "@tauri-apps/api/tauri";
 import { getVersion } from '@tauri-apps/api/app';
 import { open as openShell } from '@tauri-apps/api/shell';
 import { listen } from "@tauri-apps/api/event";
+import { save } from "@tauri-apps/api/dialog";
 import { isPermissionGranted, requestPermission, sendNotification } from "@tauri-apps/api/notification";
-import { Play, Settings, User, FileJson, Terminal, Clock, Bell, Network, Volume2, LogOut, RefreshCw, Search, CheckSquare, Square, Trash2, Plus, History, X, List, Save, Copy, Crown, ExternalLink, Upload, Download, Github, LayoutDashboard, Rocket, RotateCw } from "lucide-react";
+import { Play, Settings, User, FileJson, Terminal, Clock, Bell, Network, Volume2, LogOut, RefreshCw, Search, CheckSquare, Square, Trash2, Plus, History, X, List, Save, Copy, Crown, ExternalLink, Upload, Download, Github, LayoutDashboard, Rocket, RotateCw, Pause } from "lucide-react";
 import { QRCodeCanvas } from "qrcode.react";
 import logo from "./assets/logo.png";
 import "./App.css";
@@ -25,6 +26,56 @@ const SALES_FLAG_MAP = {
     106: "已取消",
 };
 
+const PHASE_LABELS = {
+    watching: "监控余票",
+    preparing: "准备订单",
+    creating: "提交订单",
+    fetching_payment: "获取支付",
+};
+
+// Same bound as the backend's per-task log buffer
+const TASK_LOG_LIMIT = 2000;
+
+const logFromEntry = (entry) => ({
+    seq: entry.seq,
+    time: entry.time.slice(11, 19),
+    level: entry.level,
+    message: entry.message
+});
+
+const LOG_LEVEL_CLASSES = {
+    debug: "text-gray-500",
+    warn: "text-yellow-400",
+    error: "text-red-400",
+};
+
+// Task card for a backend task record, e.g. after the window was reloaded
+const taskFromRecord = (record) => {
+    const status = {
+        scheduled: "scheduled",
+        done: "success",
+        failed: "stopped",
+        stopped: "stopped",
+    }[record.phase] || "running";
+    return {
+        id: record.task_id,
+        project: record.project_name || record.project_id,
+        screen: record.screen_name || record.screen_id,
+        sku: record.sku_name || record.sku_id,
+        buyerCount: record.buyers.length,
+        buyers: record.buyers.map(name => ({ name })),
+        startTime: record.time_start || record.started_at,
+        status,
+        phase: record.phase,
+        paused: record.paused,
+        logs: [],
+        lastLog: record.message || "",
+        paymentUrl: "",
+        accountName: record.account_uid || "Unknown",
+        args: null
+    };
+};
+
 const hasMaskChar = (value) => typeof value === "string" && value.includes("*");
 
 const pickCleanPhone = (...values) => {
@@ -39,6 +90,17 @@ const pickCleanPhone = (...values) => {
     return "";
 };
 
+// Backend commands reject with { kind, code?, message }; plain JS code throws
+// Error objects or strings.
+const formatError = (err) => {
+    if (!err) return "未知错误";
+    if (typeof err === "string") return err;
+    if (err.kind && err.message) {
+        return err.kind === "auth" ? `登录已失效: ${err.message}` : err.message;
+    }
+    return err.message || String(err);
+};
+
 const normalizeAddress = (addr) => {
     if (!addr || typeof addr !== "object") return addr;
     const cleanPhone = pickCleanPhone(
@@ -112,7 +174,7 @@ function App() {
     const [projectId, setProjectId] = useState("");
     const [projectInfo, setProjectInfo] = useState(null);
     const [projectHistory, setProjectHistory] = useState([]); // New: Project History
-    const [recentInputs, setRecentInputs] = useState([]); // LocalStorage recent inputs
+    const [recentInputs, setRecentInputs] = useState([]); // Recent project ids, saved in settings
     const [ticketInfo, setTicketInfo] = useState(""); // Legacy manual JSON input
     const [buyers, setBuyers] = useState([]);
     const [selectedBuyers, setSelectedBuyers] = useState([]);
@@ -134,17 +196,28 @@ function App() {
     // Selection State
     const [selectedScreen, setSelectedScreen] = useState(null);
     const [selectedSku, setSelectedSku] = useState(null);
+    // Ordered [{ screen, sku }] tried after selectedSku when it sells out
+    const [fallbackSkus, setFallbackSkus] = useState([]);
     const [ticketCount, setTicketCount] = useState(1);
 
     const [timeStart, setTimeStart] = useState("");
+    const [atSaleStart, setAtSaleStart] = useState(false);
     const [requestInterval, setRequestInterval] = useState(1000);
     const [mode, setMode] = useState(0); // 0: infinite, 1: finite
+    const [exclusive, setExclusive] = useState(false);
+    const [watchMode, setWatchMode] = useState(false);
+    const [watchInterval, setWatchInterval] = useState(3000);
     const [totalAttempts, setTotalAttempts] = useState(10);
+    const [timeEnd, setTimeEnd] = useState("");
+    const [maxPrepareFailures, setMaxPrepareFailures] = useState(0);
+    const [maxRequests, setMaxRequests] = useState(0);
 
     // Advanced Settings
     const [timeOffset, setTimeOffsetState] = useState(0);
     const [ntpServer, setNtpServer] = useState("https://api.bilibili.com/x/report/click/now");
-    const [syncInterval, setSyncInterval] = useState(0); // 0 = 不自动同步，只在手动操作时同步
+    const [timeSources, setTimeSources] = useState("[]"); // JSON text of settings.time_sources
+    const [syncInterval, setSyncInterval] = useState(10000); // 等待开抢时的同步间隔，0 = 只在手动操作时同步
+    const [logLevel, setLogLevel] = useState("info");
     const [lastSyncTime, setLastSyncTime] = useState(null);
     const [proxy, setProxy] = useState("");
     const [notifications, setNotifications] = useState({
@@ -229,26 +302,57 @@ function App() {
         return `${h}:${m}:${s}.${ms}`;
     };
 
-    useEffect(() => {
-        const saved = localStorage.getItem("bili_recent_inputs");
-        if (saved) {
-            try {
-                setRecentInputs(JSON.parse(saved));
-            } catch (e) { }
+    const applySettings = (settings) => {
+        setProxy(settings.proxy || "");
+        setNotifications(prev => ({ ...prev, ...settings.notifications }));
+        if (settings.ntp_server) setNtpServer(settings.ntp_server);
+        setTimeSources(JSON.stringify(settings.time_sources || [], null, 2));
+        setSyncInterval(settings.sync_interval ?? 10000);
+        if (settings.logging) setLogLevel(settings.logging.level);
+        setRecentInputs(settings.recent_inputs || []);
+        if (settings.buy) {
+            setRequestInterval(settings.buy.interval);
+            setMode(settings.buy.mode);
+            setTotalAttempts(settings.buy.total_attempts);
+            setExclusive(!!settings.buy.exclusive);
+            if (settings.buy.watch_interval) setWatchInterval(settings.buy.watch_interval);
+            setMaxPrepareFailures(settings.buy.max_prepare_failures || 0);
+            setMaxRequests(settings.buy.max_requests || 0);
         }
+    };
 
-        const savedSettings = localStorage.getItem("bili_settings");
-        if (savedSettings) {
+    // Settings written to localStorage by older versions, moved to the backend once
+    const migrateLocalSettings = () => {
+        const patch = {};
+        try {
+            const saved = JSON.parse(localStorage.getItem("bili_settings") || "null");
+            if (saved) {
+                if (saved.proxy) patch.proxy = saved.proxy;
+                if (saved.notifications) patch.notifications = saved.notifications;
+                if (saved.ntpServer) patch.ntp_server = saved.ntpServer;
+                if (saved.syncInterval) patch.sync_interval = saved.syncInterval;
+            }
+            const recent = JSON.parse(localStorage.getItem("bili_recent_inputs") || "null");
+            if (Array.isArray(recent)) patch.recent_inputs = recent;
+        } catch (e) { }
+        return patch;
+    };
+
+    useEffect(() => {
+        (async () => {
             try {
-                const settings = JSON.parse(savedSettings);
-                if (settings.proxy) setProxy(settings.proxy);
-                if (settings.notifications) setNotifications(settings.notifications);
-                if (settings.ntpServer) setNtpServer(settings.ntpServer);
-                if (settings.syncInterval) setSyncInterval(settings.syncInterval);
-                // timeOffset is usually synced on startup, but we can load it too if needed
-                // if (settings.timeOffset) updateTimeOffset(settings.timeOffset);
-            } catch (e) { }
-        }
+                let settings = await invoke("get_settings");
+                const patch = migrateLocalSettings();
+                if (Object.keys(patch).length > 0) {
+                    settings = await invoke("update_settings", { patch });
+                    localStorage.removeItem("bili_settings");
+                    localStorage.removeItem("bili_recent_inputs");
+                }
+                applySettings(settings);
+            } catch (e) {
+                console.error("Failed to load settings", e);
+            }
+        })();
     }, []);
 
     const hasScheduledTask = tasks.some(t => t.status === 'scheduled');
@@ -281,7 +385,8 @@ function App() {
             if (task_id) {
                 setTasks(prev => prev.map(t => {
                     if (t.id === task_id) {
-                        return { ...t, logs: [...t.logs, { time: timestamp, message }], lastLog: message };
+                        const logs = [...t.logs, logFromEntry(event.payload)].slice(-TASK_LOG_LIMIT);
+                        return { ...t, logs, lastLog: message };
                     }
                     return t;
                 }));
@@ -290,8 +395,8 @@ function App() {
             }
         });
 
-        const unlistenTaskResult = listen("task_result", (event) => {
-            const { task_id, success, message } = event.payload;
+        const unlistenTaskResult = listen("task_finished", (event) => {
+            const { task_id, success, message, reason } = event.payload;
 
             // Update task status
             setTasks(prev => prev.map(t => {
@@ -300,6 +405,8 @@ function App() {
                 }
                 return t;
             }));
+            // Stopped from the UI or by a task for the same buyers, nothing to notify about
+            if (reason === "stopped" || reason === "sibling_succeeded") return;
 
             // Send Notifications
             const title = success ? "抢票成功！" : "抢票任务结束";
@@ -311,18 +418,58 @@ function App() {
             });
 
             // 2. Push Channels
-            try {
-                const savedSettings = localStorage.getItem("bili_settings");
-                if (savedSettings) {
-                    const settings = JSON.parse(savedSettings);
-                    if (settings.notifications) {
-                        sendPushNotification(settings.notifications, title, message);
-                    }
-                }
-            } catch (e) { console.error(e); }
+            invoke("get_settings")
+                .then(settings => sendPushNotification(settings.notifications, title, message))
+                .catch(console.error);
+        });
+
+        const unlistenRestocked = listen("restocked", (event) => {
+            const { task_id, sku_id } = event.payload;
+            setTasks(prev => prev.map(t => t.id === task_id ? { ...t, lastLog: "检测到回流票，开始下单" } : t));
+            const title = "检测到回流票";
+            const body = `票档 ${sku_id} 可购买，任务已开始下单`;
+            sendNotification({ title, body });
+            invoke("get_settings")
+                .then(settings => sendPushNotification(settings.notifications, title, body))
+                .catch(console.error);
+        });
+
+        const unlistenPhase = listen("task_phase", (event) => {
+            const { task_id, phase } = event.payload;
+            setTasks(prev => prev.map(t => {
+                if (t.id !== task_id) return t;
+                // Leave the final status to task_finished
+                const status = t.status === "scheduled" && phase !== "scheduled" && phase !== "stopped" ? "running" : t.status;
+                return { ...t, phase, status };
+            }));
+        });
+
+        const unlistenRescheduled = listen("task_rescheduled", (event) => {
+            const { task_id, time_start } = event.payload;
+            setTasks(prev => prev.map(t => t.id === task_id ? {
+                ...t,
+                startTime: time_start,
+                lastLog: `Rescheduled to ${time_start}`,
+                args: { ...t.args, timeStart: time_start }
+            } : t));
+        });
+
+        const unlistenPaused = listen("task_paused", (event) => {
+            const { task_id, paused, phase } = event.payload;
+            setTasks(prev => prev.map(t => t.id === task_id ? { ...t, paused, phase } : t));
+        });
+
+        const unlistenAttempt = listen("attempt_result", (event) => {
+            const { task_id, attempt, errno, latency_ms } = event.payload;
+            setTasks(prev => prev.map(t => t.id === task_id ? { ...t, lastAttempt: { attempt, errno, latencyMs: latency_ms } } : t));
+        });
+
+        const unlistenSkuSwitched = listen("sku_switched", (event) => {
+            const { task_id, sku_id, sku_name } = event.payload;
+            setTasks(prev => prev.map(t => t.id === task_id ? { ...t, sku: sku_name || sku_id } : t));
         });
 
-        const unlistenPayment = listen("payment_qrcode", (event) => {
+        const unlistenPayment = listen("payment_ready", (event) => {
             const { task_id, url } = event.payload;
             if (task_id) {
                 setTasks(prev => prev.map(t => {
@@ -345,10 +492,17 @@ function App() {
             unlistenLog.then((f) => f());
             unlistenPayment.then((f) => f());
             unlistenTaskResult.then((f) => f());
+            unlistenPhase.then((f) => f());
+            unlistenRescheduled.then((f) => f());
+            unlistenPaused.then((f) => f());
+            unlistenAttempt.then((f) => f());
+            unlistenSkuSwitched.then((f) => f());
+            unlistenRestocked.then((f) => f());
         };
     }, []);
 
     async function initApp() {
+        await loadTasks();
         await loadAccounts();
         await loadHistory();
         await loadProjectHistory(); // New
@@ -356,6 +510,73 @@ function App() {
         await syncTime(true);
     }
 
+    async function loadTasks() {
+        try {
+            const records = await invoke("list_tasks");
+            setTasks(prev => {
+                const known = new Set(prev.map(t => t.id));
+                return [...prev, ...records.filter(r => !known.has(r.task_id)).map(taskFromRecord)];
+            });
+            // Lines logged before the window was (re)loaded
+            for (const record of records) {
+                invoke("get_task_logs", { taskId: record.task_id, limit: TASK_LOG_LIMIT })
+                    .then(entries => {
+                        if (entries.length === 0) return;
+                        setTasks(prev => prev.map(t => {
+                            if (t.id !== record.task_id) return t;
+                            const seen = new Set(t.logs.map(l => l.seq));
+                            const logs = [...entries.map(logFromEntry).filter(l => !seen.has(l.seq)), ...t.logs]
+                                .sort((a, b) => a.seq - b.seq)
+                                .slice(-TASK_LOG_LIMIT);
+                            return { ...t, logs };
+                        }));
+                    })
+                    .catch(console.error);
+            }
+            // Scheduled tasks saved before the last exit but too late to re-arm
+            const timestamp = new Date().toLocaleTimeString();
+            const missed = records.filter(r => r.missed_start).map(r => ({
+                time: timestamp,
+                message: `⚠️ 定时任务已错过开始时间 ${r.time_start}: ${r.project_name || r.project_id} ${r.sku_name || ""}`
+            }));
+            if (missed.length > 0) {
+                setLogs(prev => [...prev, ...missed]);
+            }
+        } catch (e) {
+            console.error("Failed to load tasks", e);
+        }
+    }
+
+    async function exportTaskLogs(task) {
+        try {
+            const path = await save({
+                defaultPath: `task-${task.id.slice(0, 8)}.log`,
+                filters: [
+                    { name: "Text", extensions: ["log", "txt"] },
+                    { name: "JSON", extensions: ["json"] }
+                ]
+            });
+            if (!path) return;
+            const format = path.toLowerCase().endsWith(".json") ? "json" : "text";
+            await invoke("export_task_logs", { taskId: task.id, path, format });
+        } catch (e) {
+            alert("导出日志失败: " + formatError(e));
+        }
+    }
+
+    function removeTask(taskId) {
+        setTasks(prev => prev.filter(t => t.id !== taskId));
+        invoke("remove_task", { taskId }).catch(console.error);
+    }
+
+    function clearFinishedTasks() {
+        const finished = tasks.filter(t => t.status === "success" || t.status === "stopped");
+        setTasks(prev => prev.filter(t => t.status !== "success" && t.status !== "stopped"));
+        for (const task of finished) {
+            invoke("remove_task", { taskId: task.id }).catch(console.error);
+        }
+    }
+
     async function loadProjectHistory() {
         try {
             const hist = await invoke("get_project_history");
@@ -397,7 +618,7 @@ function App() {
             await loadHistory();
             alert("记录已清空");
         } catch (e) {
-            alert("清空失败: " + e);
+            alert("清空失败: " + formatError(e));
         }
     }
 
@@ -462,7 +683,7 @@ function App() {
                 setUserInfo(null);
             }
         } catch (e) {
-            alert("删除失败: " + e);
+            alert("删除失败: " + formatError(e));
         }
     }
 
@@ -506,7 +727,7 @@ function App() {
             pollLogin(key);
         } catch (e) {
             console.error(e);
-            setLoginStatus("错误: " + e);
+            setLoginStatus("错误: " + formatError(e));
         }
     }
 
@@ -523,13 +744,13 @@ function App() {
                     setShowLoginModal(false);
                     setLoginStatus("");
                 } catch (e) {
-                    setLoginStatus("保存账号失败: " + e);
+                    setLoginStatus("保存账号失败: " + formatError(e));
                 }
             } else {
                 setLoginStatus(result);
             }
         } catch (e) {
-            setLoginStatus("登录失败: " + e);
+            setLoginStatus("登录失败: " + formatError(e));
         }
     }
 
@@ -555,6 +776,7 @@ function App() {
 
             if (code === 0 && response.data) {
                 setProjectInfo(response.data);
+                setFallbackSkus([]);
 
                 // Auto fetch buyers
                 fetchBuyers(null, id);
@@ -563,7 +785,7 @@ function App() {
                 if (!recentInputs.includes(id)) {
                     const newRecent = [id, ...recentInputs].slice(0, 10);
                     setRecentInputs(newRecent);
-                    localStorage.setItem("bili_recent_inputs", JSON.stringify(newRecent));
+                    invoke("update_settings", { patch: { recent_inputs: newRecent } }).catch(console.error);
                 }
 
                 // Auto-set start time if available
@@ -598,7 +820,7 @@ function App() {
                 setLogs(prev => [...prev, "获取项目信息失败: " + (response.msg || response.message || JSON.stringify(response))]);
             }
         } catch (e) {
-            setLogs(prev => [...prev, "获取项目信息失败: " + e]);
+            setLogs(prev => [...prev, "获取项目信息失败: " + formatError(e)]);
         }
     }
 
@@ -612,12 +834,19 @@ function App() {
             });
             loadProjectHistory();
         } catch (err) {
-            alert("删除失败: " + err);
+            alert("删除失败: " + formatError(err));
         }
     }
 
+    function toggleFallbackSku(screen, sku) {
+        setFallbackSkus(prev => prev.some(f => f.sku.id === sku.id)
+            ? prev.filter(f => f.sku.id !== sku.id)
+            : [...prev, { screen, sku }]);
+    }
+
     function handleSkuSelect(sku) {
         setSelectedSku(sku);
+        setFallbackSkus(prev => prev.filter(f => f.sku.id !== sku.id));
         if (sku.sale_start) {
             let timeStr = sku.sale_start;
             // If it's a timestamp (number), convert it.
@@ -664,9 +893,16 @@ function App() {
             buyerIds: selectedBuyers.map(b => b.id),
             buyerAddresses,
             timeStart,
+            atSaleStart,
             interval: requestInterval,
             mode,
             totalAttempts,
+            timeEnd,
+            maxPrepareFailures,
+            maxRequests,
+            exclusive,
+            watchMode,
+            watchInterval,
             proxy,
             timeOffset
         };
@@ -707,9 +943,16 @@ function App() {
                 }
 
                 if (config.timeStart) setTimeStart(config.timeStart);
+                if (typeof config.atSaleStart === "boolean") setAtSaleStart(config.atSaleStart);
                 if (config.interval) setRequestInterval(config.interval);
                 if (config.mode !== undefined) setMode(config.mode);
                 if (config.totalAttempts) setTotalAttempts(config.totalAttempts);
+                if (typeof config.timeEnd === "string") setTimeEnd(config.timeEnd);
+                if (config.maxPrepareFailures !== undefined) setMaxPrepareFailures(config.maxPrepareFailures);
+                if (config.maxRequests !== undefined) setMaxRequests(config.maxRequests);
+                if (typeof config.exclusive === "boolean") setExclusive(config.exclusive);
+                if (typeof config.watchMode === "boolean") setWatchMode(config.watchMode);
+                if (config.watchInterval) setWatchInterval(config.watchInterval);
                 if (config.proxy) setProxy(config.proxy);
                 if (typeof config.timeOffset !== "undefined") updateTimeOffset(config.timeOffset);
                 if (config.buyerAddresses) {
@@ -721,7 +964,7 @@ function App() {
 
                 alert("配置导入成功！(请注意：购票人需重新确认)");
             } catch (err) {
-                alert("导入失败: " + err);
+                alert("导入失败: " + formatError(err));
             }
         };
         reader.readAsText(file);
@@ -762,7 +1005,7 @@ function App() {
                 setLogs(prev => [...prev, "获取购票人失败: " + (response.msg || response.message || JSON.stringify(response))]);
             }
         } catch (e) {
-            setLogs(prev => [...prev, "获取购票人列表失败: " + e]);
+            setLogs(prev => [...prev, "获取购票人列表失败: " + formatError(e)]);
         }
     }
 
@@ -801,7 +1044,7 @@ function App() {
         try {
             // Add 5s timeout to prevent UI stuck
             const timeoutPromise = new Promise((_, reject) =>
-                setTimeout(() => reject(new Error("请求超时")), 5000)
+                setTimeout(() => reject(new Error("请求超时")), 10000)
             );
 
             const result = await Promise.race([
@@ -811,11 +1054,17 @@ function App() {
 
             let offsetNum = 0;
             let localTime = null;
+            let precision = "";
+            let source = ntpServer;
 
-            // Handle new return structure: { diff, server, local }
+            // Handle new return structure: { diff, server, local, uncertainty, rtt, samples }
             if (typeof result === 'object' && result !== null && 'diff' in result) {
                 offsetNum = Number(result.diff);
                 localTime = Number(result.local);
+                if (Number.isFinite(result.uncertainty)) {
+                    precision = ` ±${Number(result.uncertainty).toFixed(0)}ms, RTT ${Number(result.rtt).toFixed(0)}ms, ${result.samples} 个样本`;
+                }
+                if (result.source) source = result.source;
             } else {
                 // Fallback for legacy return (f64)
                 offsetNum = Number(result);
@@ -831,12 +1080,12 @@ function App() {
                 }
 
                 if (!silent) {
-                    setLogs(prev => [...prev, `时间已同步，偏移量: ${offsetNum.toFixed(0)}ms (Server: ${ntpServer})`]);
+                    setLogs(prev => [...prev, `时间已同步，偏移量: ${offsetNum.toFixed(0)}ms${precision} (Server: ${source})`]);
                 }
             }
         } catch (e) {
             if (!silent) {
-                setLogs(prev => [...prev, "时间同步失败: " + e]);
+                setLogs(prev => [...prev, "时间同步失败: " + formatError(e)]);
             }
         } finally {
             if (!silent) setIsSyncing(false);
@@ -1043,7 +1292,16 @@ function App() {
             is_hot_project: false,
             pay_money: selectedSku.price,
             contact_name: topName,
-            contact_tel: topTel
+            contact_tel: topTel,
+            fallbacks: fallbackSkus
+                .filter(f => f.sku.id !== selectedSku.id)
+                .map(({ screen, sku }) => ({
+                    screen_id: String(screen.id),
+                    screen_name: screen.name,
+                    sku_id: String(sku.id),
+                    sku_name: sku.desc,
+                    price: sku.price
+                }))
         };
 
         finalTicketInfo = JSON.stringify(payload);
@@ -1057,7 +1315,14 @@ function App() {
             proxy,
             timeOffset: parseFloat(timeOffset),
             buyers: sanitizedBuyers,
-            ntpServer
+            ntpServer,
+            exclusive,
+            watch: watchMode,
+            watchInterval: parseInt(watchInterval),
+            atSaleStart,
+            timeEnd,
+            maxPrepareFailures: parseInt(maxPrepareFailures) || 0,
+            maxRequests: parseInt(maxRequests) || 0
         };
     }
 
@@ -1093,7 +1358,7 @@ function App() {
                 syncLog = `时间已自动校准，偏移量: ${safeOffset}ms`;
                 setLogs(prev => [...prev, syncLog]);
             } catch (e) {
-                syncLog = "时间自动校准失败: " + e;
+                syncLog = "时间自动校准失败: " + formatError(e);
                 setLogs(prev => [...prev, syncLog]);
             }
 
@@ -1121,6 +1386,14 @@ function App() {
                     buyerInfo: parsedTicket?.buyer_info
                 });
 
+                // The backend picked the start time from the published sale start
+                let scheduledAt = timeStart;
+                if (args.atSaleStart) {
+                    const record = await invoke("get_task", { taskId });
+                    scheduledAt = record.time_start || "";
+                    setLogs(prev => [...prev, scheduledAt ? `开售时间: ${scheduledAt}` : "已开售，立即开始"]);
+                }
+
                 const newTask = {
                     id: taskId,
                     project: projectInfo?.name || projectId,
@@ -1128,10 +1401,10 @@ function App() {
                     sku: selectedSku?.desc || "Default",
                     buyerCount: selectedBuyers.length,
                     buyers: selectedBuyers, // Store all buyers
-                    startTime: timeStart || new Date().toLocaleTimeString(),
-                    status: timeStart ? "scheduled" : "running",
+                    startTime: scheduledAt || new Date().toLocaleTimeString(),
+                    status: scheduledAt ? "scheduled" : "running",
                     logs: [syncLog],
-                    lastLog: timeStart ? `Waiting for ${timeStart}` : `Starting for ${selectedBuyers.length} buyers...`,
+                    lastLog: scheduledAt ? `Waiting for ${scheduledAt}` : `Starting for ${selectedBuyers.length} buyers...`,
                     paymentUrl: "",
                     accountName: userInfo?.uname || "Unknown",
                     args: args
@@ -1145,13 +1418,13 @@ function App() {
                 }
 
             } catch (err) {
-                setLogs(prev => [...prev, `❌ 启动失败: ${err.message || err}`]);
-                alert(`启动失败: ${err.message || err}`);
+                setLogs(prev => [...prev, `❌ 启动失败: ${formatError(err)}`]);
+                alert(`启动失败: ${formatError(err)}`);
             }
 
         } catch (e) {
-            setLogs((prev) => [...prev, "启动流程异常: " + e]);
-            alert("启动流程异常: " + e);
+            setLogs((prev) => [...prev, "启动流程异常: " + formatError(e)]);
+            alert("启动流程异常: " + formatError(e));
         }
     }
 
@@ -1163,7 +1436,7 @@ function App() {
             }
 
             // Auto-start scheduled tasks
-            if (timeStart) {
+            if (timeStart || atSaleStart) {
                 startBuy();
                 return;
             }
@@ -1192,7 +1465,7 @@ function App() {
             setActiveTab("tasks");
             alert(`已保存任务到任务列表`);
         } catch (e) {
-            alert("保存任务失败: " + e);
+            alert("保存任务失败: " + formatError(e));
         }
     }
 
@@ -1215,7 +1488,7 @@ function App() {
 
             setTasks(prev => [runningTask, ...prev]);
         } catch (e) {
-            alert("启动任务失败: " + e);
+            alert("启动任务失败: " + formatError(e));
             // Put it back if failed? Or just leave it removed? 
             // Better to keep it as pending if failed, but for simplicity let's just alert.
         }
@@ -1230,6 +1503,16 @@ function App() {
         }
     }
 
+    async function togglePauseTask(task) {
+        try {
+            await invoke(task.paused ? "resume_task" : "pause_task", { taskId: task.id });
+            setTasks(prev => prev.map(t => t.id === task.id ? { ...t, paused: !task.paused } : t));
+        } catch (e) {
+            console.error("Pause/resume task failed", e);
+            setTasks(prev => prev.map(t => t.id === task.id ? { ...t, lastLog: formatError(e) } : t));
+        }
+    }
+
     async function startAllTasks() {
         const pendingTasks = tasks.filter(t => t.status === 'pending');
         if (pendingTasks.length === 0) {
@@ -1262,27 +1545,13 @@ function App() {
 
         const scheduledTasks = tasks.filter(t => t.status === 'scheduled');
         if (scheduledTasks.length > 0) {
-            if (confirm(`发现 ${scheduledTasks.length} 个正在倒计时的任务，是否也要更新它们的时间？(这将重启这些任务)`)) {
+            if (confirm(`发现 ${scheduledTasks.length} 个正在倒计时的任务，是否也要更新它们的时间？`)) {
                 for (const task of scheduledTasks) {
-                    await stopTask(task.id);
-                    const newArgs = { ...task.args, timeStart: newTime };
                     try {
-                        const newTaskId = await invoke("start_buy", newArgs);
-                        setTasks(prev => {
-                            const filtered = prev.filter(t => t.id !== task.id);
-                            const newTask = {
-                                ...task,
-                                id: newTaskId,
-                                startTime: newTime,
-                                status: "scheduled",
-                                logs: [],
-                                lastLog: `Rescheduled to ${newTime}`,
-                                args: newArgs
-                            };
-                            return [newTask, ...filtered];
-                        });
+                        await invoke("reschedule_task", { taskId: task.id, newTime });
                     } catch (e) {
-                        console.error("Failed to restart task", task.id, e);
+                        console.error("Failed to reschedule task", task.id, e);
+                        setTasks(prev => prev.map(t => t.id === task.id ? { ...t, lastLog: "改时失败: " + formatError(e) } : t));
                     }
                 }
             }
@@ -1314,7 +1583,7 @@ function App() {
             a.click();
             URL.revokeObjectURL(url);
         } catch (e) {
-            alert("导出失败: " + e);
+            alert("导出失败: " + formatError(e));
         }
     }
 
@@ -1335,7 +1604,7 @@ function App() {
                 await loadAccounts();
                 alert("导入成功！");
             } catch (err) {
-                alert("导入失败: " + err);
+                alert("导入失败: " + formatError(err));
             }
         };
         reader.readAsText(file);
@@ -1355,16 +1624,29 @@ function App() {
         </button>
     );
 
-    function handleSaveSettings() {
-        const settings = {
-            proxy,
+    async function handleSaveSettings() {
+        let parsedSources;
+        try {
+            parsedSources = JSON.parse(timeSources || "[]");
+            if (!Array.isArray(parsedSources)) throw new Error("需要是数组");
+        } catch (e) {
+            alert("对时源格式错误: " + formatError(e));
+            return;
+        }
+        const patch = {
+            proxy: proxy || null,
             notifications,
-            timeOffset,
-            ntpServer,
-            syncInterval
+            ntp_server: ntpServer || null,
+            time_sources: parsedSources,
+            sync_interval: syncInterval,
+            logging: { level: logLevel }
         };
-        localStorage.setItem("bili_settings", JSON.stringify(settings));
-        alert("设置已保存");
+        try {
+            await invoke("update_settings", { patch });
+            alert("设置已保存");
+        } catch (e) {
+            alert("保存设置失败: " + formatError(e));
+        }
     }
 
     async function handleTestPush(type) {
@@ -1685,7 +1967,7 @@ function App() {
                                             </div>
                                         </button>
                                     </div>
-                                    <button onClick={() => setTasks([])} className="text-sm text-gray-500 hover:text-white">
+                                    <button onClick={clearFinishedTasks} className="text-sm text-gray-500 hover:text-white">
                                         清空已完成任务
                                     </button>
                                 </div>
@@ -1721,6 +2003,8 @@ function App() {
                                                         </span>
                                                         {task.status === 'pending' && <span className="text-[10px] bg-yellow-500/20 text-yellow-400 px-1.5 py-0.5 rounded">待启动</span>}
                                                         {task.status === 'scheduled' && <span className="text-[10px] bg-blue-500/20 text-blue-400 px-1.5 py-0.5 rounded animate-pulse">定时</span>}
+                                                        {task.status === 'running' && PHASE_LABELS[task.phase] && <span className="text-[10px] bg-green-500/20 text-green-400 px-1.5 py-0.5 rounded">{PHASE_LABELS[task.phase]}</span>}
+                                                        {task.paused && (task.status === 'running' || task.status === 'scheduled') && <span className="text-[10px] bg-yellow-500/20 text-yellow-400 px-1.5 py-0.5 rounded">已暂停</span>}
                                                     </div>
                                                     <div className="text-xs text-gray-400 truncate mt-0.5">
                                                         {task.project}
@@ -1742,6 +2026,15 @@ function App() {
                                                         <Play size={14} />
                                                     </button>
                                                 )}
+                                                {(task.status === 'running' || task.status === 'scheduled') && (
+                                                    <button
+                                                        onClick={() => togglePauseTask(task)}
+                                                        className="p-1.5 bg-yellow-900/30 text-yellow-400 hover:bg-yellow-900/50 rounded text-xs font-bold border border-yellow-900/50"
+                                                        title={task.paused ? "继续" : "暂停"}
+                                                    >
+                                                        {task.paused ? <Play size={14} /> : <Pause size={14} />}
+                                                    </button>
+                                                )}
                                                 {(task.status === 'running' || task.status === 'scheduled') && (
                                                     <button
                                                         onClick={() => stopTask(task.id)}
@@ -1752,7 +2045,14 @@ function App() {
                                                     </button>
                                                 )}
                                                 <button
-                                                    onClick={() => setTasks(prev => prev.filter(t => t.id !== task.id))}
+                                                    onClick={() => exportTaskLogs(task)}
+                                                    className="p-1.5 text-gray-500 hover:text-blue-400"
+                                                    title="导出日志"
+                                                >
+                                                    <Download size={14} />
+                                                </button>
+                                                <button
+                                                    onClick={() => removeTask(task.id)}
                                                     className="p-1.5 text-gray-500 hover:text-red-400"
                                                     title="删除任务"
                                                 >
@@ -1763,6 +2063,11 @@ function App() {
                                         {viewMode === "list" && (
                                             <div className="text-xs text-gray-500 font-mono mt-1 ml-7">
                                                 {task.startTime}
+                                                {task.lastAttempt && (
+                                                    <span className="ml-3">
+                                                        第 {task.lastAttempt.attempt} 次 · errno {task.lastAttempt.errno ?? "-"} · {task.lastAttempt.latencyMs}ms
+                                                    </span>
+                                                )}
                                             </div>
                                         )}
 
@@ -1795,7 +2100,7 @@ function App() {
                                         {/* Logs Preview */}
                                         <div className={`bg-black font-mono text-xs overflow-y-auto custom-scrollbar ${viewMode === "grid" ? "flex-1 p-2" : "rounded-lg p-3 h-32 bg-black/50"}`}>
                                             {(viewMode === "grid" ? task.logs : task.logs.slice(-10)).map((log, i) => (
-                                                <div key={i} className="text-gray-300 break-all border-b border-gray-800/50 last:border-0 py-0.5">
+                                                <div key={log.seq ?? i} className={`${LOG_LEVEL_CLASSES[log.level] || "text-gray-300"} break-all border-b border-gray-800/50 last:border-0 py-0.5`}>
                                                     <span className="text-gray-600 mr-1">[{log.time}]</span>
                                                     {log.message}
                                                 </div>
@@ -2055,6 +2360,21 @@ function App() {
                                                                                 {sku.sale_start}
                                                                             </div>
                                                                         )}
+                                                                        {selectedSku?.id !== sku.id && (
+                                                                            <span
+                                                                                role="button"
+                                                                                onClick={(e) => {
+                                                                                    e.stopPropagation();
+                                                                                    toggleFallbackSku(selectedScreen, sku);
+                                                                                }}
+                                                                                className={`inline-block text-[10px] mt-1 px-1 rounded border ${fallbackSkus.some(f => f.sku.id === sku.id)
+                                                                                    ? "border-yellow-500 text-yellow-300"
+                                                                                    : "border-gray-600 text-gray-400 hover:text-gray-200"
+                                                                                    }`}
+                                                                            >
+                                                                                {fallbackSkus.some(f => f.sku.id === sku.id) ? `备选 ${fallbackSkus.findIndex(f => f.sku.id === sku.id) + 1}` : "+ 备选"}
+                                                                            </span>
+                                                                        )}
                                                                         {/* 显示SKU标签 */}
                                                                         {sku.tags && Array.isArray(sku.tags) && sku.tags.length > 0 && (
                                                                             <div className="flex flex-wrap gap-1 mt-1">
@@ -2068,6 +2388,19 @@ function App() {
                                                                 );
                                                             })}
                                                         </div>
+                                                        {fallbackSkus.filter(f => f.sku.id !== selectedSku?.id).length > 0 && (
+                                                            <div className="mt-3 text-xs text-gray-400">
+                                                                <div className="mb-1">备选票档 (当前票档无票或不可售时按顺序切换)</div>
+                                                                <div className="flex flex-wrap gap-2">
+                                                                    {fallbackSkus.filter(f => f.sku.id !== selectedSku?.id).map((f, i) => (
+                                                                        <span key={f.sku.id} className="flex items-center gap-1 px-2 py-1 rounded border border-yellow-700/50 bg-yellow-900/20 text-yellow-200">
+                                                                            {i + 1}. {f.screen.name} / {f.sku.desc} ￥{f.sku.price / 100}
+                                                                            <X size={12} className="cursor-pointer hover:text-white" onClick={() => toggleFallbackSku(f.screen, f.sku)} />
+                                                                        </span>
+                                                                    ))}
+                                                                </div>
+                                                            </div>
+                                                        )}
                                                     </div>
                                                 )}
                                             </div>
@@ -2283,9 +2616,10 @@ function App() {
                                                         <input
                                                             type="datetime-local"
                                                             step="1"
-                                                            className="flex-1 bg-gray-900 border border-gray-700 rounded-lg p-2.5 text-white focus:border-blue-500 focus:outline-none font-mono text-sm"
+                                                            className="flex-1 bg-gray-900 border border-gray-700 rounded-lg p-2.5 text-white focus:border-blue-500 focus:outline-none font-mono text-sm disabled:opacity-50"
                                                             value={timeStart.replace(' ', 'T')}
                                                             onChange={(e) => setTimeStart(e.target.value.replace('T', ' '))}
+                                                            disabled={atSaleStart}
                                                         />
                                                         <button
                                                             onClick={() => {
@@ -2328,6 +2662,10 @@ function App() {
                                                             </button>
                                                         ))}
                                                     </div>
+                                                    <label className="flex items-center gap-2 text-xs text-gray-300 cursor-pointer">
+                                                        <input type="checkbox" checked={atSaleStart} onChange={(e) => setAtSaleStart(e.target.checked)} />
+                                                        按开售时间：读取所选票档的开售时间，开售前 1 分钟再次确认
+                                                    </label>
                                                 </div>
                                             </div>
                                             <div>
@@ -2351,6 +2689,42 @@ function App() {
                                             />
                                         </div>
 
+                                        <div>
+                                            <label className="block text-sm font-medium text-gray-400 mb-2">停止条件 (0 或留空表示不限制)</label>
+                                            <div className="grid grid-cols-3 gap-4">
+                                                <div>
+                                                    <div className="text-xs text-gray-500 mb-1">结束时间</div>
+                                                    <input
+                                                        type="datetime-local"
+                                                        step="1"
+                                                        className="w-full bg-gray-900 border border-gray-700 rounded-lg p-2.5 text-white focus:border-blue-500 focus:outline-none font-mono text-sm"
+                                                        value={timeEnd.replace(' ', 'T')}
+                                                        onChange={(e) => setTimeEnd(e.target.value.replace('T', ' '))}
+                                                    />
+                                                </div>
+                                                <div>
+                                                    <div className="text-xs text-gray-500 mb-1">连续准备失败次数</div>
+                                                    <input
+                                                        type="number"
+                                                        min="0"
+                                                        className="w-full bg-gray-900 border border-gray-700 rounded-lg p-2.5 text-white focus:border-blue-500 focus:outline-none"
+                                                        value={maxPrepareFailures}
+                                                        onChange={(e) => setMaxPrepareFailures(e.target.value)}
+                                                    />
+                                                </div>
+                                                <div>
+                                                    <div className="text-xs text-gray-500 mb-1">总请求次数</div>
+                                                    <input
+                                                        type="number"
+                                                        min="0"
+                                                        className="w-full bg-gray-900 border border-gray-700 rounded-lg p-2.5 text-white focus:border-blue-500 focus:outline-none"
+                                                        value={maxRequests}
+                                                        onChange={(e) => setMaxRequests(e.target.value)}
+                                                    />
+                                                </div>
+                                            </div>
+                                        </div>
+
                                         <div>
                                             <label className="block text-sm font-medium text-gray-400 mb-2">运行模式</label>
                                             <div className="flex gap-4">
@@ -2365,6 +2739,26 @@ function App() {
                                                     <div className="text-xs text-gray-400">尝试 {totalAttempts} 次后停止</div>
                                                 </label>
                                             </div>
+                                            <label className="flex items-center gap-2 mt-3 text-sm text-gray-300 cursor-pointer">
+                                                <input type="checkbox" checked={watchMode} onChange={(e) => setWatchMode(e.target.checked)} />
+                                                回流票监控：定时查询票档库存，有票时才开始下单，售罄后继续监控
+                                            </label>
+                                            {watchMode && (
+                                                <div className="flex items-center gap-2 mt-2 ml-6 text-sm text-gray-400">
+                                                    查询间隔 (ms)
+                                                    <input
+                                                        type="number"
+                                                        min="1000"
+                                                        className="w-28 bg-gray-900 border border-gray-700 rounded p-1 text-white focus:border-blue-500 focus:outline-none"
+                                                        value={watchInterval}
+                                                        onChange={(e) => setWatchInterval(e.target.value)}
+                                                    />
+                                                </div>
+                                            )}
+                                            <label className="flex items-center gap-2 mt-3 text-sm text-gray-300 cursor-pointer">
+                                                <input type="checkbox" checked={exclusive} onChange={(e) => setExclusive(e.target.checked)} />
+                                                互斥任务：同一账号、同一组购票人的任务有一个下单成功后，其余任务自动停止
+                                            </label>
                                         </div>
 
                                         <div>
@@ -2487,6 +2881,16 @@ function App() {
                                             </div>
                                             <p className="text-xs text-gray-500 mt-1">支持 HTTP API (如 B站/淘宝) 或 NTP 服务器域名</p>
                                         </div>
+                                        <div className="md:col-span-2">
+                                            <label className="block text-sm font-medium text-gray-400 mb-2">对时源 (JSON，按顺序尝试，失败后依次使用上方服务器和 B站 API)</label>
+                                            <textarea
+                                                className="w-full h-32 bg-gray-900 border border-gray-700 rounded-lg p-3 font-mono text-xs text-gray-300 focus:border-blue-500 focus:outline-none"
+                                                value={timeSources}
+                                                onChange={(e) => setTimeSources(e.target.value)}
+                                                placeholder='[{ "url": "https://api.m.taobao.com/rest/api3.do?api=mtop.common.getTimestamp", "pointer": "/data/t", "unit": "ms" }, { "url": "https://www.baidu.com", "date_header": true }]'
+                                            />
+                                            <p className="text-xs text-gray-500 mt-1">url 为 HTTP 地址或 NTP 域名；pointer 为 JSON Pointer，设置 pointer 时必须填写 unit (s 或 ms)；date_header 为 true 时读取响应的 Date 头</p>
+                                        </div>
                                         <div>
                                             <label className="block text-sm font-medium text-gray-400 mb-2">同步间隔 (毫秒，0 = 不自动同步)</label>
                                             <input
@@ -2496,6 +2900,20 @@ function App() {
                                                 onChange={(e) => setSyncInterval(parseInt(e.target.value) || 0)}
                                             />
                                         </div>
+                                        <div>
+                                            <label className="block text-sm font-medium text-gray-400 mb-2">日志文件级别</label>
+                                            <select
+                                                className="w-full bg-gray-900 border border-gray-700 rounded-lg p-3 text-white focus:border-blue-500 focus:outline-none"
+                                                value={logLevel}
+                                                onChange={(e) => setLogLevel(e.target.value)}
+                                            >
+                                                <option value="debug">Debug (含请求详情)</option>
+                                                <option value="info">Info</option>
+                                                <option value="warn">Warn</option>
+                                                <option value="error">Error</option>
+                                            </select>
+                                            <p className="text-xs text-gray-500 mt-1">日志写入应用目录下的 logs/，按天及大小轮转</p>
+                                        </div>
                                         <div>
                                             <label className="block text-sm font-medium text-gray-400 mb-2">当前时间偏移 (ms)</label>
                                             <div className="flex gap-2">